@@ -1,9 +1,7 @@
-use std::borrow::{BorrowMut, Borrow};
 
 #[derive(PartialEq, Copy, Clone, Debug)]
 pub struct Addressing {
     pub register: Option<AddressingRegistry>,
-    pub add_cycles: bool,
     pub mode: AddressingMode
 }
 
@@ -32,112 +30,89 @@ pub enum AddressingRegistry {
 }
 
 impl Addressing {
-    pub fn indexed_indirect() -> Addressing {
+    pub const fn indexed_indirect() -> Addressing {
         Addressing {
             register: None,
-            add_cycles: false,
             mode: AddressingMode::IndexedIndirect
         }
     }
 
-    pub fn indirect_indexed() -> Addressing {
+    pub const fn indirect_indexed() -> Addressing {
         Addressing {
             register: None,
-            add_cycles: true,
             mode: AddressingMode::IndirectIndexed
         }
     }
 
-    pub fn zero_page() -> Addressing {
+    pub const fn zero_page() -> Addressing {
         Addressing {
             register: None,
-            add_cycles: true,
             mode: AddressingMode::ZeroPage
         }
     }
 
-    pub fn immediate(addressing_registry: Option<AddressingRegistry>) -> Addressing {
+    pub const fn immediate() -> Addressing {
         Addressing {
-            register: addressing_registry,
-            add_cycles: true,
+            register: None,
             mode: AddressingMode::Immediate
         }
     }
 
-    pub fn absolute() -> Addressing {
+    pub const fn absolute() -> Addressing {
         Addressing {
             register: None,
-            add_cycles: false,
             mode: AddressingMode::Absolute
         }
     }
 
-    pub fn absolute_indexed(reg: Option<AddressingRegistry>, add_cycles: bool) -> Addressing {
+    pub const fn absolute_indexed(reg: AddressingRegistry) -> Addressing {
         Addressing {
-            register: reg,
-            add_cycles,
+            register: Some(reg),
             mode: AddressingMode::AbsoluteIndexed
         }
     }
 
-    pub fn zero_page_indexed(reg: Option<AddressingRegistry>, add_cycles: bool) -> Addressing {
+    pub const fn zero_page_indexed(reg: AddressingRegistry) -> Addressing {
         Addressing {
-            register: reg,
-            add_cycles,
+            register: Some(reg),
             mode: AddressingMode::ZeroPageIndexed
         }
     }
 
-    pub fn accumulator() -> Addressing {
+    pub const fn accumulator() -> Addressing {
         Addressing {
             register: None,
-            add_cycles: false,
             mode: AddressingMode::Accumulator
         }
     }
 
-    pub fn indirect() -> Addressing {
+    pub const fn indirect() -> Addressing {
         Addressing {
             register: None,
-            add_cycles: false,
             mode: AddressingMode::Indirect
         }
     }
 
-    pub fn relative() -> Addressing {
+    pub const fn relative() -> Addressing {
         Addressing {
             register: None,
-            add_cycles: false,
             mode: AddressingMode::Relative
         }
     }
 
-    pub fn from_op_code(mid_op_code: u8, lower_op_code: u8) -> Addressing {
-        match (mid_op_code, lower_op_code) {
-            // c == 0b10
-            (0b0, 0b01) => Addressing::indexed_indirect(),
-            (0b001, 0b01) => Addressing::zero_page(),
-            (0b010, 0b01) => Addressing::immediate(None),
-            (0b011, 0b01) => Addressing::absolute(),
-            (0b100, 0b01) => Addressing::indirect_indexed(),
-            (0b101, 0b01) => Addressing::zero_page_indexed(Some(AddressingRegistry::X), false),
-            (0b110, 0b01) => Addressing::absolute_indexed(Some(AddressingRegistry::Y), true),
-            (0b111, 0b01) => Addressing::absolute_indexed(Some(AddressingRegistry::X), false),
-            // c == 0b10
-            (0b000, 0b10) => Addressing::immediate(None),
-            (0b001, 0b10) => Addressing::zero_page(),
-            (0b010, 0b10) => Addressing::accumulator(),
-            (0b011, 0b10) => Addressing::absolute(),
-            (0b101, 0b10) => Addressing::zero_page_indexed(Some(AddressingRegistry::X), false),
-            (0b111, 0b10) => Addressing::absolute_indexed(Some(AddressingRegistry::X), false),
-            // c == 00
-            (0b000, 0b00) => Addressing::immediate(None),
-            (0b001, 0b00) => Addressing::zero_page(),
-            (0b011, 0b00) => Addressing::absolute(),
-            (0b101, 0b00) => Addressing::zero_page_indexed(Some(AddressingRegistry::X), false),
-            (0b111, 0b00) => Addressing::absolute_indexed(Some(AddressingRegistry::X), false),
-            (_, 0b0) => Addressing::relative(),
-            _ => panic!("Unknown addressing type")
+    pub const fn implied() -> Addressing {
+        Addressing {
+            register: None,
+            mode: AddressingMode::Implied
+        }
+    }
+
+    // Instruction length including the op code itself
+    pub const fn bytes(&self) -> u8 {
+        match self.mode {
+            AddressingMode::Implied | AddressingMode::Accumulator => 1,
+            AddressingMode::Absolute | AddressingMode::AbsoluteIndexed | AddressingMode::Indirect => 3,
+            _ => 2
         }
     }
 }
@@ -0,0 +1,404 @@
+use std::collections::HashMap;
+use std::fmt;
+use crate::op_code::OP_CODE_TABLE;
+use crate::addressing::{Addressing, AddressingMode, AddressingRegistry};
+
+static IMP: Addressing = Addressing::implied();
+static ACC: Addressing = Addressing::accumulator();
+static IMM: Addressing = Addressing::immediate();
+static ZP0: Addressing = Addressing::zero_page();
+static ZPX: Addressing = Addressing::zero_page_indexed(AddressingRegistry::X);
+static ZPY: Addressing = Addressing::zero_page_indexed(AddressingRegistry::Y);
+static ABS: Addressing = Addressing::absolute();
+static ABX: Addressing = Addressing::absolute_indexed(AddressingRegistry::X);
+static ABY: Addressing = Addressing::absolute_indexed(AddressingRegistry::Y);
+static IND: Addressing = Addressing::indirect();
+static IZX: Addressing = Addressing::indexed_indirect();
+static IZY: Addressing = Addressing::indirect_indexed();
+static REL: Addressing = Addressing::relative();
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AssemblyError {
+    pub line: usize,
+    pub message: String
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+enum Statement {
+    Instruction { op_code: u8, addressing: Addressing, operand: Option<String> },
+    Bytes(Vec<String>),
+    Words(Vec<String>),
+    Origin(u16)
+}
+
+struct Line {
+    number: usize,
+    address: u16,
+    statement: Statement
+}
+
+// Assembles source into bytes starting at the origin, .org can only move forward and the gap is zero filled.
+// Labels end with a colon, numbers are $hex, %binary or decimal, <label and >label take the low and high byte
+pub fn assemble(source: &str, origin: u16) -> Result<Vec<u8>, AssemblyError> {
+    let mut labels = HashMap::new();
+    let lines = parse(source, origin, &mut labels)?;
+    let mut bytes = vec![];
+    for line in lines.iter() {
+        let error = |message: String| AssemblyError { line: line.number, message };
+        if let Statement::Origin(address) = line.statement {
+            if (address as usize) < origin as usize + bytes.len() {
+                return Err(error(format!(".org ${:04X} moves backwards", address)))
+            }
+            bytes.resize((address - origin) as usize, 0);
+            continue
+        }
+        emit(line, &labels, &mut bytes).map_err(error)?;
+    }
+    Ok(bytes)
+}
+
+// First pass: resolves labels and picks an addressing mode, and so a size, for every instruction
+fn parse(source: &str, origin: u16, labels: &mut HashMap<String, u16>) -> Result<Vec<Line>, AssemblyError> {
+    let mut lines = vec![];
+    let mut address = origin as u32;
+    for (index, text) in source.lines().enumerate() {
+        let number = index + 1;
+        let error = |message: String| AssemblyError { line: number, message };
+        let mut text = strip_comment(text).trim();
+        while let Some(colon) = text.find(':') {
+            let label = text[..colon].trim();
+            if !is_identifier(label) {
+                break
+            }
+            if labels.insert(label.to_string(), address as u16).is_some() {
+                return Err(error(format!("Label {} defined twice", label)))
+            }
+            text = text[colon + 1..].trim();
+        }
+        if text.is_empty() {
+            continue
+        }
+        let (keyword, operand) = match text.find(char::is_whitespace) {
+            Some(split) => (&text[..split], Some(text[split..].trim())),
+            None => (text, None)
+        };
+        let statement = match keyword.to_lowercase().as_str() {
+            ".org" => {
+                let target = evaluate(operand.unwrap_or(""), labels).map_err(error)?
+                    .ok_or_else(|| error(String::from(".org needs a value known in advance")))?;
+                address = target as u32;
+                Statement::Origin(target)
+            },
+            ".byte" | ".db" => Statement::Bytes(split_arguments(operand.unwrap_or(""))),
+            ".word" | ".dw" => Statement::Words(split_arguments(operand.unwrap_or(""))),
+            _ => parse_instruction(keyword, operand, labels).map_err(error)?
+        };
+        let start = address;
+        address += size(&statement) as u32;
+        if address > 0x10000 {
+            return Err(error(String::from("Program runs past $FFFF")))
+        }
+        lines.push(Line { number, address: start as u16, statement });
+    }
+    Ok(lines)
+}
+
+fn parse_instruction(mnemonic: &str, operand: Option<&str>, labels: &HashMap<String, u16>) -> Result<Statement, String> {
+    let mnemonic = mnemonic.to_uppercase();
+    let (candidates, operand): (Vec<Addressing>, Option<String>) = match operand {
+        None => (vec![IMP, ACC], None),
+        Some(operand) if operand.eq_ignore_ascii_case("a") => (vec![ACC], None),
+        Some(operand) => {
+            let upper = operand.to_uppercase().replace(' ', "");
+            if let Some(value) = operand.strip_prefix('#') {
+                (vec![IMM], Some(value.trim().to_string()))
+            } else if upper.starts_with('(') && upper.ends_with(",X)") {
+                (vec![IZX], Some(inner(operand, ",")))
+            } else if upper.starts_with('(') && upper.ends_with("),Y") {
+                (vec![IZY], Some(inner(operand, ")")))
+            } else if upper.starts_with('(') && upper.ends_with(')') {
+                (vec![IND], Some(inner(operand, ")")))
+            } else if upper.ends_with(",X") || upper.ends_with(",Y") {
+                let value = operand[..operand.rfind(',').unwrap()].trim().to_string();
+                let zero_page = is_zero_page(&value, labels)?;
+                let candidates = match (upper.ends_with(",X"), zero_page) {
+                    (true, true) => vec![ZPX, ABX],
+                    (true, false) => vec![ABX],
+                    (false, true) => vec![ZPY, ABY],
+                    (false, false) => vec![ABY]
+                };
+                (candidates, Some(value))
+            } else if is_zero_page(operand, labels)? {
+                (vec![REL, ZP0, ABS], Some(operand.to_string()))
+            } else {
+                (vec![REL, ABS], Some(operand.to_string()))
+            }
+        }
+    };
+    for addressing in candidates {
+        if let Some(op_code) = find_op_code(&mnemonic, addressing) {
+            return Ok(Statement::Instruction { op_code, addressing, operand })
+        }
+    }
+    if OP_CODE_TABLE.iter().any(|info| info.mnemonic.to_string() == mnemonic) {
+        Err(format!("{} does not support this addressing mode", mnemonic))
+    } else {
+        Err(format!("Unknown mnemonic {}", mnemonic))
+    }
+}
+
+// Official encodings win over the unofficial duplicates (NOP, SBC #)
+fn find_op_code(mnemonic: &str, addressing: Addressing) -> Option<u8> {
+    let matching: Vec<usize> = (0..OP_CODE_TABLE.len())
+        .filter(|op_code| {
+            let info = &OP_CODE_TABLE[*op_code];
+            info.addressing == addressing && info.mnemonic.to_string() == mnemonic
+        })
+        .collect();
+    matching.iter()
+        .find(|op_code| OP_CODE_TABLE[**op_code].official)
+        .or_else(|| matching.first())
+        .map(|op_code| *op_code as u8)
+}
+
+fn size(statement: &Statement) -> usize {
+    match statement {
+        Statement::Instruction { addressing, .. } => addressing.bytes() as usize,
+        Statement::Bytes(arguments) => arguments.iter()
+            .map(|argument| string_literal(argument).map_or(1, |string| string.len()))
+            .sum(),
+        Statement::Words(arguments) => arguments.len() * 2,
+        Statement::Origin(_) => 0
+    }
+}
+
+// Second pass: every label is known, operands are evaluated and encoded
+fn emit(line: &Line, labels: &HashMap<String, u16>, bytes: &mut Vec<u8>) -> Result<(), String> {
+    let resolve = |expression: &str| -> Result<u16, String> {
+        evaluate(expression, labels)?.ok_or_else(|| format!("Unknown label in {}", expression))
+    };
+    match &line.statement {
+        Statement::Instruction { op_code, addressing, operand } => {
+            bytes.push(*op_code);
+            let value = match operand {
+                Some(operand) => resolve(operand)?,
+                None => return Ok(())
+            };
+            match addressing.mode {
+                AddressingMode::Relative => {
+                    let offset = value as i32 - (line.address as i32 + 2);
+                    if !(-128..=127).contains(&offset) {
+                        return Err(format!("Branch target ${:04X} out of range", value))
+                    }
+                    bytes.push(offset as i8 as u8);
+                },
+                AddressingMode::Absolute | AddressingMode::AbsoluteIndexed | AddressingMode::Indirect => {
+                    bytes.push(value as u8);
+                    bytes.push((value >> 8) as u8);
+                },
+                _ => {
+                    if value > 0xFF {
+                        return Err(format!("Operand ${:04X} does not fit in a byte", value))
+                    }
+                    bytes.push(value as u8);
+                }
+            }
+        },
+        Statement::Bytes(arguments) => {
+            for argument in arguments {
+                match string_literal(argument) {
+                    Some(string) => bytes.extend(string.bytes()),
+                    None => {
+                        let value = resolve(argument)?;
+                        if value > 0xFF {
+                            return Err(format!(".byte value ${:04X} does not fit in a byte", value))
+                        }
+                        bytes.push(value as u8);
+                    }
+                }
+            }
+        },
+        Statement::Words(arguments) => {
+            for argument in arguments {
+                let value = resolve(argument)?;
+                bytes.push(value as u8);
+                bytes.push((value >> 8) as u8);
+            }
+        },
+        Statement::Origin(_) => {}
+    }
+    Ok(())
+}
+
+// Sum of terms, None while a label is not defined yet
+fn evaluate(expression: &str, labels: &HashMap<String, u16>) -> Result<Option<u16>, String> {
+    let expression = expression.trim();
+    if expression.is_empty() {
+        return Err(String::from("Missing operand"))
+    }
+    if let Some(rest) = expression.strip_prefix('<') {
+        return Ok(evaluate(rest, labels)?.map(|value| value & 0xFF))
+    }
+    if let Some(rest) = expression.strip_prefix('>') {
+        return Ok(evaluate(rest, labels)?.map(|value| value >> 8))
+    }
+    let mut total: i32 = 0;
+    let mut sign = 1;
+    let mut term = String::new();
+    for character in expression.chars().chain(std::iter::once('+')) {
+        if (character == '+' || character == '-') && !term.trim().is_empty() {
+            match evaluate_term(term.trim(), labels)? {
+                Some(value) => total += sign * value as i32,
+                None => return Ok(None)
+            }
+            sign = if character == '+' { 1 } else { -1 };
+            term.clear();
+        } else {
+            term.push(character);
+        }
+    }
+    Ok(Some(total as u16))
+}
+
+fn evaluate_term(term: &str, labels: &HashMap<String, u16>) -> Result<Option<u16>, String> {
+    let parsed = if let Some(hex) = term.strip_prefix('$') {
+        u16::from_str_radix(hex, 16)
+    } else if let Some(binary) = term.strip_prefix('%') {
+        u16::from_str_radix(binary, 2)
+    } else if term.chars().all(|character| character.is_ascii_digit()) {
+        term.parse::<u16>()
+    } else if is_identifier(term) {
+        return Ok(labels.get(term).copied())
+    } else {
+        return Err(format!("Invalid value {}", term))
+    };
+    parsed.map(Some).map_err(|_| format!("Invalid number {}", term))
+}
+
+fn is_zero_page(expression: &str, labels: &HashMap<String, u16>) -> Result<bool, String> {
+    Ok(evaluate(expression, labels)?.is_some_and(|value| value <= 0xFF))
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut characters = text.chars();
+    characters.next().is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && characters.all(|character| character.is_ascii_alphanumeric() || character == '_')
+}
+
+// Text between the opening parenthesis and the first terminator
+fn inner(operand: &str, terminator: &str) -> String {
+    let start = operand.find('(').unwrap() + 1;
+    let end = operand[start..].find(terminator).unwrap() + start;
+    operand[start..end].trim().to_string()
+}
+
+fn string_literal(argument: &str) -> Option<&str> {
+    argument.strip_prefix('"').and_then(|rest| rest.strip_suffix('"'))
+}
+
+fn strip_comment(text: &str) -> &str {
+    let mut in_string = false;
+    for (index, character) in text.char_indices() {
+        match character {
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &text[..index],
+            _ => {}
+        }
+    }
+    text
+}
+
+fn split_arguments(text: &str) -> Vec<String> {
+    let mut arguments = vec![];
+    let mut current = String::new();
+    let mut in_string = false;
+    for character in text.chars() {
+        match character {
+            '"' => {
+                in_string = !in_string;
+                current.push(character);
+            },
+            ',' if !in_string => {
+                arguments.push(current.trim().to_string());
+                current.clear();
+            },
+            _ => current.push(character)
+        }
+    }
+    if !current.trim().is_empty() {
+        arguments.push(current.trim().to_string());
+    }
+    arguments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_addressing_modes() {
+        let source = "
+            LDA #$44
+            LDA $44
+            LDA $44,X
+            LDX $44,Y
+            LDA $4400
+            LDA $4400,X
+            LDA $4400,Y
+            LDA ($44,X)
+            LDA ($44),Y
+            JMP ($4400)
+            ASL A
+            ASL
+            CLC";
+        assert_eq!(assemble(source, 0x8000), Ok(vec![
+            0xA9, 0x44, 0xA5, 0x44, 0xB5, 0x44, 0xB6, 0x44, 0xAD, 0x00, 0x44,
+            0xBD, 0x00, 0x44, 0xB9, 0x00, 0x44, 0xA1, 0x44, 0xB1, 0x44,
+            0x6C, 0x00, 0x44, 0x0A, 0x0A, 0x18
+        ]))
+    }
+
+    #[test]
+    fn test_labels_and_branches() {
+        let source = "
+            start:  LDX #%00000011 ; count down
+            loop:   DEX
+                    BNE loop
+                    JMP end
+                    .byte 1, \"ok\"
+            end:    .word start, end+1
+                    LDA <end
+                    LDA #>end";
+        assert_eq!(assemble(source, 0xC000), Ok(vec![
+            0xA2, 0x03, 0xCA, 0xD0, 0xFD, 0x4C, 0x0B, 0xC0, 0x01, b'o', b'k',
+            0x00, 0xC0, 0x0C, 0xC0, 0xA5, 0x0B, 0xA9, 0xC0
+        ]))
+    }
+
+    #[test]
+    fn test_unofficial_and_directives() {
+        let source = "
+            NOP
+            NOP $10
+            LAX ($10),Y
+            SBC #$01
+            .org $0008
+            ISB $0200,X";
+        assert_eq!(assemble(source, 0x0000), Ok(vec![
+            0xEA, 0x04, 0x10, 0xB3, 0x10, 0xE9, 0x01, 0x00, 0xFF, 0x00, 0x02
+        ]))
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(assemble("LDA ($4400),Y", 0).unwrap_err().message, "Operand $4400 does not fit in a byte");
+        assert_eq!(assemble("STA #$01", 0).unwrap_err().message, "STA does not support this addressing mode");
+        assert_eq!(assemble("\nFOO $01", 0).unwrap_err(), AssemblyError { line: 2, message: String::from("Unknown mnemonic FOO") });
+        assert!(assemble("BNE far\n.org $0100\nfar: NOP", 0).is_err())
+    }
+}
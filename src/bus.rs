@@ -1,13 +1,9 @@
-use std::path::Path;
-use std::fs::File;
-use log::{info, warn};
-use std::io::Read;
-use dirs::home_dir;
+use log::info;
 use std::fmt::Debug;
-use crate::cartridge::{Cartridge, CartridgeLoader};
-use crate::ppu::Ppu;
-use crate::cpu::Cpu;
-use crate::screen::Screen;
+use crate::cartridge::Cartridge;
+use crate::controller::{ButtonState, Controller};
+use crate::save_state::{Snapshot, StateReader, StateWriter};
+use crate::ppu::{Colour, Ppu};
 
 static RAM_MIRROR_BOUNDARY: u16 = 0x07FF;
 static RAM_BOUNDARY: u16 = 0x1FFF;
@@ -18,14 +14,64 @@ static PPU_BOUNDARY: u16 = 0x3FFF;
 static CARTRIDGE_LOWER_BOUNDARY: u16 = 0x4020;
 static MEMORY_MAP_BOUNDARY: u16 = 0xFFFF;
 
+static CONTROLLER_PORT_1: u16 = 0x4016;
+static CONTROLLER_PORT_2: u16 = 0x4017;
+// Only the low bits of the controller ports are driven, the rest are left from the last bus value
+static CONTROLLER_OPEN_BUS_MASK: u8 = 0b1110_0000;
+
 static APU_LOWER_BOUNDARY: u16 = 0x4000;
 static APU_UPPER_BOUNDARY: u16 = 0x401F;
 
+bitflags! {
+    // Devices pulling the shared /IRQ line low, the line stays asserted while any of them does
+    pub struct IrqSource: u8 {
+        const FRAME_COUNTER = 0b0000_0001;
+        const DMC = 0b0000_0010;
+        const MAPPER = 0b0000_0100;
+    }
+}
+
+// Everything the CPU sees through its address and data pins
+pub trait CpuBus {
+    fn fetch(&mut self, address: u16) -> u8;
+
+    fn store(&mut self, value: u8, address: u16);
+
+    // What a fetch would return, without the side effects of reading I/O registers
+    fn peek(&self, address: u16) -> u8;
+
+    // Advances everything clocked alongside the CPU by a single CPU cycle
+    fn tick(&mut self);
+
+    // Edge detected /NMI, stays latched until the CPU acknowledges it
+    fn nmi(&self) -> bool;
+
+    fn acknowledge_nmi(&mut self);
+
+    // Level triggered /IRQ
+    fn irq(&self) -> bool;
+
+    // Scanline and dot the PPU is about to render, for buses with a PPU attached
+    fn ppu_position(&self) -> (u16, u16) {
+        (0, 0)
+    }
+
+    // Page written to $4014 since the last call, the CPU halts while it is copied into OAM
+    fn take_oam_dma(&mut self) -> Option<u8> {
+        None
+    }
+}
+
 #[derive(Debug)]
 pub struct Bus {
     memory: Vec<u8>,
     ppu: Ppu,
     cartridge: Cartridge,
+    irq_sources: IrqSource,
+    controllers: [Controller; 2],
+    // Last value driven on the data bus, read back from addresses nothing answers to
+    open_bus: u8,
+    oam_dma: Option<u8>,
     pub nmi: bool
 }
 
@@ -35,45 +81,116 @@ impl Bus {
             memory,
             ppu,
             cartridge,
+            irq_sources: IrqSource::empty(),
+            controllers: [Controller::new(), Controller::new()],
+            open_bus: 0,
+            oam_dma: None,
             nmi: false
         }
     }
 
-    pub fn emulate(&mut self, screen: &mut Screen) {
-        let previous_state = self.ppu.nmi_occurred;
-        self.ppu.emulate(screen);
-        self.nmi = !previous_state & self.ppu.nmi_occurred;
+    // Driven by the APU and mappers, neither of which is emulated yet
+    #[allow(dead_code)]
+    pub fn set_irq(&mut self, source: IrqSource, active: bool) {
+        self.irq_sources.set(source, active);
     }
 
-    pub fn fetch(&mut self, address: u16) -> u8 {
-        if self.is_ram(address) {
+    // Buttons held on the pad plugged into the port, 0 for $4016 and 1 for $4017
+    pub fn set_buttons(&mut self, port: usize, buttons: ButtonState) {
+        self.controllers[port].set_buttons(buttons);
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.ppu.frame()
+    }
+
+    pub fn frame_buffer(&self) -> &[Colour] {
+        self.ppu.frame_buffer()
+    }
+
+    // Latched on the falling edge of /NMI until the CPU services it, a single instruction spans many ticks
+    fn latch_nmi(&mut self, previous_line: bool) {
+        if !previous_line && self.ppu.nmi_line() {
+            self.nmi = true;
+        }
+    }
+
+    fn is_oamdma(&self, address: u16) -> bool {
+        address == 0x4014
+    }
+
+    fn is_controller(&self, address: u16) -> bool {
+        (address == CONTROLLER_PORT_1) | (address == CONTROLLER_PORT_2)
+    }
+
+    fn as_controller_port(&self, address: u16) -> usize {
+        (address - CONTROLLER_PORT_1) as usize
+    }
+
+    fn is_apu(&self, address: u16) -> bool {
+        (address >= APU_LOWER_BOUNDARY) & (address <= APU_UPPER_BOUNDARY)
+    }
+
+    fn is_ram(&self, address: u16) -> bool {
+        address <= RAM_BOUNDARY
+    }
+
+    fn is_ppu(&self, address: u16) -> bool {
+        (address > RAM_BOUNDARY) & (address <= PPU_BOUNDARY)
+    }
+
+    fn is_cartridge(&self, address: u16) -> bool {
+        (address >= CARTRIDGE_LOWER_BOUNDARY) & (address <= MEMORY_MAP_BOUNDARY)
+    }
+
+    fn as_ram_address(&self, address: u16) -> u16 {
+        address & RAM_MIRROR_BOUNDARY
+    }
+
+    fn as_ppu_address(&self, address: u16) -> u16 {
+        address & PPU_MIRROR_BOUNDARY
+    }
+}
+
+impl CpuBus for Bus {
+    fn fetch(&mut self, address: u16) -> u8 {
+        let value = if self.is_ram(address) {
             self.memory[self.as_ram_address(address) as usize]
         } else if self.is_ppu(address) {
             self.ppu.fetch(self.as_ppu_address(address))
         } else if self.is_cartridge(address) {
-            self.cartridge.cpu_read(address)
+            self.cartridge.cpu_read(address).unwrap_or(self.open_bus)
+        } else if self.is_controller(address) {
+            let port = self.as_controller_port(address);
+            self.controllers[port].read() | (self.open_bus & CONTROLLER_OPEN_BUS_MASK)
         } else if self.is_apu(address) {
             info!("Accessing APU");
-            return 0;
+            self.open_bus
         } else {
             panic!("Memory address not supported, {:#01X}", address)
-        }
+        };
+        self.open_bus = value;
+        value
     }
 
-    pub fn store(&mut self, value: u8, address: u16) {
+    fn store(&mut self, value: u8, address: u16) {
+        self.open_bus = value;
         if self.is_ram(address) {
             let as_ram_address = self.as_ram_address(address) as usize;
             info!("Storing value {:#01X} at address {:#01X}", value, as_ram_address);
             self.memory[as_ram_address] = value;
         } else if self.is_ppu(address) {
-            self.ppu.save(self.as_ppu_address(address), value)
+            // Setting PPUCTRL bit 7 during vblank pulls /NMI low straight away
+            let previous_line = self.ppu.nmi_line();
+            self.ppu.save(self.as_ppu_address(address), value);
+            self.latch_nmi(previous_line);
         } else if self.is_oamdma(address) {
-            // TODO: Add 514 cpu cycles here
-            let address = ((value as u16) << 8) as usize;
-            let oamdma = &self.memory[address..(address + 256)];
-            self.ppu.write_oamdma(oamdma)
+            self.oam_dma = Some(value);
         } else if self.is_cartridge(address) {
-            unimplemented!();
+            self.cartridge.cpu_write(address, value);
+        } else if address == CONTROLLER_PORT_1 {
+            // Both pads share the strobe line, $4017 writes go to the APU frame counter
+            self.controllers.iter_mut().for_each(|controller| controller.write(value));
         } else if self.is_apu(address) {
             info!("Writing APU");
         } else {
@@ -81,31 +198,129 @@ impl Bus {
         }
     }
 
-    fn is_oamdma(&self, address: u16) -> bool {
-        return address == 0x4014
+    fn peek(&self, address: u16) -> u8 {
+        if self.is_ram(address) {
+            self.memory[self.as_ram_address(address) as usize]
+        } else if self.is_ppu(address) {
+            self.ppu.peek(self.as_ppu_address(address))
+        } else if self.is_cartridge(address) {
+            self.cartridge.cpu_peek(address).unwrap_or(self.open_bus)
+        } else if self.is_controller(address) {
+            let port = self.as_controller_port(address);
+            self.controllers[port].peek() | (self.open_bus & CONTROLLER_OPEN_BUS_MASK)
+        } else if self.is_apu(address) {
+            self.open_bus
+        } else {
+            panic!("Memory address not supported, {:#01X}", address)
+        }
     }
 
-    fn is_apu(&self, address: u16) -> bool {
-        return (address >= APU_LOWER_BOUNDARY) & (address <= APU_UPPER_BOUNDARY)
+    fn tick(&mut self) {
+        let previous_line = self.ppu.nmi_line();
+        self.ppu.emulate();
+        self.latch_nmi(previous_line);
     }
 
-    fn is_ram(&self, address: u16) -> bool {
-        return address <= RAM_BOUNDARY
+    fn nmi(&self) -> bool {
+        self.nmi
     }
 
-    fn is_ppu(&self, address: u16) -> bool {
-        return (address > RAM_BOUNDARY) & (address <= PPU_BOUNDARY)
+    fn acknowledge_nmi(&mut self) {
+        self.nmi = false;
     }
 
-    fn is_cartridge(&self, address: u16) -> bool {
-        return (address >= CARTRIDGE_LOWER_BOUNDARY) & (address <= MEMORY_MAP_BOUNDARY)
+    fn irq(&self) -> bool {
+        !self.irq_sources.is_empty()
     }
 
-    fn as_ram_address(&self, address: u16) -> u16 {
-        return address & RAM_MIRROR_BOUNDARY
+    fn ppu_position(&self) -> (u16, u16) {
+        (self.ppu.scanline(), self.ppu.dot())
     }
 
-    fn as_ppu_address(&self, address: u16) -> u16 {
-        return address & PPU_MIRROR_BOUNDARY
+    fn take_oam_dma(&mut self) -> Option<u8> {
+        self.oam_dma.take()
+    }
+}
+
+impl Snapshot for Bus {
+    fn snapshot(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.memory);
+        writer.write_u8(self.irq_sources.bits());
+        writer.write_u8(self.open_bus);
+        writer.write_bool(self.nmi);
+        for controller in self.controllers.iter() {
+            controller.snapshot(writer);
+        }
+        self.ppu.snapshot(writer);
+        self.cartridge.snapshot(writer);
+    }
+
+    fn restore(&mut self, reader: &mut StateReader) -> Result<(), String> {
+        reader.read_into(&mut self.memory)?;
+        self.irq_sources = IrqSource::from_bits_truncate(reader.read_u8()?);
+        self.open_bus = reader.read_u8()?;
+        self.nmi = reader.read_bool()?;
+        for controller in self.controllers.iter_mut() {
+            controller.restore(reader)?;
+        }
+        self.ppu.restore(reader)?;
+        self.cartridge.restore(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ppu::NameTableMirroring;
+
+    #[test]
+    fn test_open_bus() {
+        let mut bus = Bus::new(vec![0; 0x800], Ppu::new(vec![], NameTableMirroring::VERTICAL), Cartridge::new());
+        bus.store(0x5A, 0x0010);
+        assert_eq!(bus.fetch(0x4018), 0x5A);
+        assert_eq!(bus.fetch(0x0011), 0x00);
+        assert_eq!(bus.fetch(0x5000), 0x00);
+        bus.fetch(0x0010);
+        assert_eq!(bus.peek(0x8000), 0x5A);
+        assert_eq!(bus.fetch(0x8000), 0x5A)
+    }
+
+    #[test]
+    fn test_controller_ports() {
+        let mut bus = Bus::new(vec![0; 0x800], Ppu::new(vec![], NameTableMirroring::VERTICAL), Cartridge::new());
+        bus.set_buttons(0, ButtonState::B);
+        bus.set_buttons(1, ButtonState::A);
+        bus.store(1, 0x4016);
+        bus.store(0, 0x4016);
+        // LDA $4016 leaves the high byte of the address on the bus
+        bus.store(0x40, 0x0000);
+        assert_eq!(bus.fetch(0x4016), 0x40);
+        assert_eq!(bus.fetch(0x4016), 0x41);
+        assert_eq!(bus.fetch(0x4017), 0x41);
+        assert_eq!(bus.peek(0x4017), 0x40)
+    }
+
+    fn tick_into_vblank(bus: &mut Bus) {
+        while bus.ppu_position().0 != 242 {
+            bus.tick();
+        }
+    }
+
+    #[test]
+    fn test_no_nmi_when_disabled() {
+        let mut bus = Bus::new(vec![0; 0x800], Ppu::new(vec![], NameTableMirroring::VERTICAL), Cartridge::new());
+        bus.store(0x00, 0x2000);
+        tick_into_vblank(&mut bus);
+        assert!(!bus.nmi())
+    }
+
+    #[test]
+    fn test_nmi_enabled_during_vblank() {
+        let mut bus = Bus::new(vec![0; 0x800], Ppu::new(vec![], NameTableMirroring::VERTICAL), Cartridge::new());
+        bus.store(0x00, 0x2000);
+        tick_into_vblank(&mut bus);
+        assert!(!bus.nmi());
+        bus.store(0x80, 0x2000);
+        assert!(bus.nmi())
     }
 }
@@ -1,17 +1,23 @@
-use crate::util::{combine_nibbles, nth_bit};
-use log::{info, warn};
-use std::fs::File;
-use std::io::Read;
+use crate::util::nth_bit;
+use log::trace;
 use crate::ppu::NameTableMirroring;
 use crate::ppu::NameTableMirroring::{HORIZONTAL, VERTICAL};
+use crate::save_state::{checksum, Snapshot, StateReader, StateWriter};
 
 static PRG_ROM_SIZE_FLAG: u8 = 4;
 
+static PRG_RAM_LOWER_BOUNDARY: u16 = 0x6000;
+static PRG_RAM_UPPER_BOUNDARY: u16 = 0x7FFF;
+static PRG_RAM_SIZE: usize = 0x2000;
+
 trait Mapper {
-    fn map_cpu(prg_rom: &Vec<u8>, banks: u8, address: u16) -> u8;
+    // None when nothing on the cartridge answers, the CPU then sees open bus
+    fn map_cpu(prg_rom: &[u8], banks: u8, address: u16) -> Option<u8>;
 
-    // TODO: Remove, not used
-    fn map_ppu(chr_rom: &Vec<u8>, address: u16) -> u8;
+    // Same lookup as map_cpu, mappers latching state on reads must leave it untouched here
+    fn peek_cpu(prg_rom: &[u8], banks: u8, address: u16) -> Option<u8> {
+        Self::map_cpu(prg_rom, banks, address)
+    }
 }
 
 // TODO: trait
@@ -20,19 +26,12 @@ struct Mapper000 {}
 
 impl Mapper for Mapper000 {
 
-    fn map_cpu(prg_rom: &Vec<u8>, banks: u8, address: u16) -> u8 {
-        if address >= 0x8000 && address <= 0xFFFF {
+    fn map_cpu(prg_rom: &[u8], banks: u8, address: u16) -> Option<u8> {
+        if address >= 0x8000 {
             let mapped_address = address & (if banks > 1 {0x7FFF} else {0x3FFF});
-            return prg_rom[mapped_address as usize]
+            return prg_rom.get(mapped_address as usize).copied()
         }
-        panic!("Unknown cpu address to map: {:X}", address);
-    }
-
-    fn map_ppu(chr_rom: &Vec<u8>, address: u16) -> u8 {
-        if 0x0000 >= address && 0x1FFF <= address {
-            return chr_rom[address as usize]
-        }
-        panic!("Unknown ppu address to map: {:X}", address);
+        None
     }
 }
 
@@ -40,6 +39,7 @@ impl Mapper for Mapper000 {
 pub struct Cartridge {
     prg_rom_banks: u8,
     prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
     pub chr_rom: Vec<u8>,
     mapper_code: u8,
     pub nametable_mirroring: NameTableMirroring
@@ -47,10 +47,12 @@ pub struct Cartridge {
 
 impl Cartridge {
 
-    // TODO: Add mocking, use only for testing
+    // An empty NROM board for tests
+    #[cfg(test)]
     pub fn new() -> Cartridge {
-        return Cartridge {
+        Cartridge {
             prg_rom: vec![],
+            prg_ram: vec![0; PRG_RAM_SIZE],
             chr_rom: vec![],
             mapper_code: 0,
             prg_rom_banks: 0,
@@ -58,29 +60,46 @@ impl Cartridge {
         }
     }
 
-    pub fn cpu_read(&mut self, address: u16) -> u8 {
-        return self.map_cpu_address(address);
+    pub fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        if self.is_prg_ram(address) {
+            return Some(self.prg_ram[(address - PRG_RAM_LOWER_BOUNDARY) as usize])
+        }
+        self.map_cpu_address(address)
     }
 
-    pub fn ppu_read(&mut self, address: u16) -> u8 {
-        return self.map_ppu_address(address)
+    pub fn cpu_peek(&self, address: u16) -> Option<u8> {
+        if self.is_prg_ram(address) {
+            return Some(self.prg_ram[(address - PRG_RAM_LOWER_BOUNDARY) as usize])
+        }
+        self.peek_cpu_address(address)
+    }
+
+    pub fn prg_rom(&self) -> &[u8] {
+        &self.prg_rom
     }
 
     pub fn cpu_write(&mut self, address: u16, value: u8) {
-        let mapped = self.map_cpu_address(address);
-        // TODO
+        if self.is_prg_ram(address) {
+            self.prg_ram[(address - PRG_RAM_LOWER_BOUNDARY) as usize] = value;
+        } else {
+            trace!("Ignoring write of {:#01X} to cartridge ROM at {:#01X}", value, address);
+        }
+    }
+
+    fn is_prg_ram(&self, address: u16) -> bool {
+        (address >= PRG_RAM_LOWER_BOUNDARY) & (address <= PRG_RAM_UPPER_BOUNDARY)
     }
 
-    fn map_cpu_address(&mut self, address: u16) -> u8 {
+    fn map_cpu_address(&mut self, address: u16) -> Option<u8> {
         match self.mapper_code {
             000 => Mapper000::map_cpu(&self.prg_rom, self.prg_rom_banks, address),
             _ => panic!("Unknown mapper code")
         }
     }
 
-    fn map_ppu_address(&mut self, address: u16) -> u8 {
+    fn peek_cpu_address(&self, address: u16) -> Option<u8> {
         match self.mapper_code {
-            000 => Mapper000::map_ppu(&self.chr_rom, address),
+            000 => Mapper000::peek_cpu(&self.prg_rom, self.prg_rom_banks, address),
             _ => panic!("Unknown mapper code")
         }
     }
@@ -100,9 +119,10 @@ impl CartridgeLoader {
         let prg_rom_banks = loader.prg_banks();
         let chr_rom = loader.load_chr();
         let nametable_mirroring = loader.load_nametable_mirroring();
-        return Cartridge {
+        Cartridge {
             prg_rom_banks,
             prg_rom,
+            prg_ram: vec![0; PRG_RAM_SIZE],
             chr_rom,
             mapper_code,
             nametable_mirroring
@@ -124,27 +144,26 @@ impl CartridgeLoader {
         let upper_mapper_flag = 7;
         let lower_nibble = (self.payload[lower_mapper_flag] & 0x10) >> 4;
         let upper_nibble = self.payload[upper_mapper_flag] & 0x10;
-        let mapper_code = lower_nibble | upper_nibble;
-        return mapper_code
+        lower_nibble | upper_nibble
     }
 
     fn prg_banks(&mut self) -> u8 {
-        return self.payload[PRG_ROM_SIZE_FLAG as usize]
+        self.payload[PRG_ROM_SIZE_FLAG as usize]
     }
 
     fn prg_size(&mut self) -> u16 {
-        return self.payload[PRG_ROM_SIZE_FLAG as usize] as u16 * 16 * 1024; // 16KB * size
+        self.payload[PRG_ROM_SIZE_FLAG as usize] as u16 * 16 * 1024// 16KB * size
     }
 
     fn chr_size(&mut self) -> u16 {
         let chr_size_flag = 5;
-        return self.payload[chr_size_flag] as u16 * 8 * 1024 // 8KB * size!!
+        self.payload[chr_size_flag] as u16 * 8 * 1024 // 8KB * size!!
     }
 
     fn trainer_offset(&mut self) -> u16 {
         let trainer_flag = 6;
         let has_trainer = nth_bit(self.payload[trainer_flag], 2); // TODO: Check bit
-        return if has_trainer {
+        if has_trainer {
             512
         } else {
             0
@@ -154,7 +173,7 @@ impl CartridgeLoader {
     fn load_nametable_mirroring(&mut self) -> NameTableMirroring {
         let nametable_flag = 6;
         let mirroring = nth_bit(self.payload[nametable_flag], 0);
-        return if mirroring {
+        if mirroring {
             VERTICAL
         } else {
             HORIZONTAL
@@ -165,15 +184,54 @@ impl CartridgeLoader {
         let header_offset = 16;
         let prg_start = (header_offset + self.trainer_offset()) as usize; // HEADER - 16 bytes + Trainer 512 BYTES
         let size = self.prg_size() as usize;
-        return self.payload[prg_start..(prg_start + size)].to_vec()
+        self.payload[prg_start..(prg_start + size)].to_vec()
     }
 
     fn load_chr(&mut self) -> Vec<u8> {
         let header_offset = 16;
         let trainer_offset = self.trainer_offset();
-        let prg_offset = self.prg_size() as u16;
+        let prg_offset = self.prg_size();
         let chr_size = self.chr_size() as usize;
         let chr_start = (header_offset + trainer_offset + prg_offset) as usize;
-        return self.payload[chr_start..(chr_start + chr_size)].to_vec()
+        self.payload[chr_start..(chr_start + chr_size)].to_vec()
+    }
+}
+
+// ROM is not saved, only checked, a state only loads into the game it was made with.
+// NROM has no bank registers, mappers that do write them after PRG-RAM
+impl Snapshot for Cartridge {
+    fn snapshot(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.mapper_code);
+        writer.write_u32(checksum(&self.prg_rom));
+        writer.write_bytes(&self.prg_ram);
+    }
+
+    fn restore(&mut self, reader: &mut StateReader) -> Result<(), String> {
+        let mapper_code = reader.read_u8()?;
+        if mapper_code != self.mapper_code || reader.read_u32()? != checksum(&self.prg_rom) {
+            return Err(String::from("save state was made with a different cartridge"))
+        }
+        reader.read_into(&mut self.prg_ram)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prg_ram() {
+        let mut cartridge = Cartridge::new();
+        cartridge.cpu_write(0x6000, 0x80);
+        cartridge.cpu_write(0x7FFF, 0x12);
+        assert_eq!(cartridge.cpu_read(0x6000), Some(0x80));
+        assert_eq!(cartridge.cpu_read(0x7FFF), Some(0x12))
+    }
+
+    #[test]
+    fn test_unmapped_reads() {
+        let mut cartridge = Cartridge::new();
+        assert_eq!(cartridge.cpu_read(0x5000), None);
+        assert_eq!(cartridge.cpu_peek(0x8000), None)
     }
 }
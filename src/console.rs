@@ -3,23 +3,150 @@ extern crate winit;
 use crate::screen::Screen;
 use crate::bus::Bus;
 use crate::cpu::Cpu;
-use crate::cartridge::CartridgeLoader;
+use crate::cartridge::{Cartridge, CartridgeLoader};
 use crate::ppu::Ppu;
 use crate::util::read_file;
+use crate::trace::Tracer;
+use crate::input::{Input, KeyMap};
+use crate::movie::MovieSession;
+use crate::rewind::Rewind;
+use crate::save_state::{Snapshot, StateReader, StateWriter};
 use std::path::Path;
-use std::fs::File;
 use self::winit::event_loop::EventLoop;
 
 pub struct Console {}
 
 impl Console {
-    pub fn power(cartridge_path: &Path, logfile: &File) {
-        let cartridge = CartridgeLoader::load_cartridge(read_file(&cartridge_path));
-        let mut ppu = Ppu::new(cartridge.chr_rom.clone(), cartridge.nametable_mirroring);
-        let mut bus = Bus::new(vec![0; 2048], ppu, cartridge);
-        let mut cpu = Cpu::new(bus, None);
+    pub fn power(cartridge_path: &Path, tracer: Option<Tracer>, key_map: KeyMap, movie: Option<MovieSession>,
+                 rewind: Rewind) {
+        let cartridge = CartridgeLoader::load_cartridge(read_file(cartridge_path));
+        let mut cpu = Console::boot(cartridge);
+        cpu.set_tracer(tracer);
         let event_loop = EventLoop::new();
         let mut screen = Screen::new(&event_loop);
-        cpu.emulation_loop(logfile, &mut screen, event_loop)
+        let mut input = Input::new(key_map);
+        cpu.emulation_loop(&mut screen, &mut input, movie, rewind, cartridge_path, event_loop)
     }
-}
\ No newline at end of file
+
+    // Wires the cartridge to the PPU and bus and powers the CPU on, no window is needed
+    pub fn boot(cartridge: Cartridge) -> Cpu {
+        let ppu = Ppu::new(cartridge.chr_rom.clone(), cartridge.nametable_mirroring);
+        let bus = Bus::new(vec![0; 2048], ppu, cartridge);
+        Cpu::new(bus, None)
+    }
+
+    // Snapshot of the CPU, RAM, PPU, controllers and cartridge RAM and registers
+    pub fn save_state(cpu: &Cpu) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        cpu.snapshot(&mut writer);
+        writer.finish()
+    }
+
+    // A state that fails to load leaves the console as it was
+    pub fn load_state(cpu: &mut Cpu, state: &[u8]) -> Result<(), String> {
+        let backup = Console::save_state(cpu);
+        let result = Console::restore(cpu, state);
+        if result.is_err() {
+            Console::restore(cpu, &backup).unwrap();
+        }
+        result
+    }
+
+    fn restore(cpu: &mut Cpu, state: &[u8]) -> Result<(), String> {
+        let mut reader = StateReader::new(state)?;
+        cpu.restore(&mut reader)?;
+        reader.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::bus::CpuBus;
+    use crate::trace::format_line;
+    use std::fs;
+
+    // nestest in automation mode, compared line by line against the Nintendulator log
+    #[test]
+    #[ignore = "needs rom/nestest.nes and testing/nestest.log, run with cargo test -- --ignored"]
+    fn test_nestest() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let rom_path = root.join("rom/nestest.nes");
+        let log_path = root.join("testing/nestest.log");
+        assert!(rom_path.exists() && log_path.exists(), "rom/nestest.nes and testing/nestest.log are required");
+        let expected_log = fs::read_to_string(log_path).unwrap();
+        let mut cpu = Console::boot(CartridgeLoader::load_cartridge(read_file(&rom_path)));
+        cpu.set_program_counter(0xC000);
+
+        for (line, expected) in expected_log.lines().enumerate() {
+            let state = cpu.state();
+            let actual = format_line(cpu.bus(), &state);
+            if actual != expected.trim_end() {
+                panic!(
+                    "nestest diverged at line {}\nexpected: {}\nactual:   {}\n{:?}",
+                    line + 1, expected, actual, cpu
+                );
+            }
+            cpu.emulate();
+        }
+
+        assert_eq!(cpu.bus().peek(0x02), 0x00, "official opcode failure code");
+        assert_eq!(cpu.bus().peek(0x03), 0x00, "unofficial opcode failure code")
+    }
+
+    // Keeps adding up a counter in PRG-RAM, the NMI handler only returns
+    fn boot_counter(step: u8) -> Cpu {
+        let program = assemble(&format!("
+                    LDX #0
+            loop:   TXA
+                    CLC
+                    ADC #{}
+                    TAX
+                    STA $6000
+                    INC $10
+                    JMP loop
+            nmi:    RTI", step), 0x8000).unwrap();
+        let mut payload = vec![0x4E, 0x45, 0x53, 0x1A, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let mut prg_rom = vec![0; 0x4000];
+        prg_rom[..program.len()].copy_from_slice(&program);
+        prg_rom[0x3FFA] = (program.len() - 1) as u8;
+        prg_rom[0x3FFB] = 0x80;
+        prg_rom[0x3FFD] = 0x80;
+        payload.append(&mut prg_rom);
+        Console::boot(CartridgeLoader::load_cartridge(payload))
+    }
+
+    fn run_cycles(cpu: &mut Cpu, cycles: u64) {
+        let end = cpu.total_cycles() + cycles;
+        while cpu.total_cycles() < end {
+            cpu.emulate();
+        }
+    }
+
+    #[test]
+    fn test_save_and_load_state() {
+        let mut cpu = boot_counter(3);
+        run_cycles(&mut cpu, 40_000);
+        let state = Console::save_state(&cpu);
+        run_cycles(&mut cpu, 50_000);
+        let expected = Console::save_state(&cpu);
+
+        Console::load_state(&mut cpu, &state).unwrap();
+        assert_eq!(Console::save_state(&cpu), state);
+        run_cycles(&mut cpu, 50_000);
+        assert_eq!(Console::save_state(&cpu), expected)
+    }
+
+    #[test]
+    fn test_load_state_from_another_cartridge() {
+        let mut cpu = boot_counter(3);
+        let mut other = boot_counter(5);
+        run_cycles(&mut cpu, 1_000);
+        let before = Console::save_state(&cpu);
+        let error = Console::load_state(&mut cpu, &Console::save_state(&other)).unwrap_err();
+        assert_eq!(error, "save state was made with a different cartridge");
+        assert_eq!(Console::save_state(&cpu), before);
+        assert!(Console::load_state(&mut other, &before[..before.len() - 1]).is_err())
+    }
+}
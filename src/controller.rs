@@ -0,0 +1,114 @@
+use crate::save_state::{Snapshot, StateReader, StateWriter};
+
+bitflags! {
+    // Buttons in the order the shift register reports them, A first
+    pub struct ButtonState: u8 {
+        const A = 0b0000_0001;
+        const B = 0b0000_0010;
+        const SELECT = 0b0000_0100;
+        const START = 0b0000_1000;
+        const UP = 0b0001_0000;
+        const DOWN = 0b0010_0000;
+        const LEFT = 0b0100_0000;
+        const RIGHT = 0b1000_0000;
+    }
+}
+
+impl Default for ButtonState {
+    fn default() -> ButtonState {
+        ButtonState::empty()
+    }
+}
+
+// Standard pad, a 4021 shift register latched while the strobe is high
+#[derive(Debug, Default)]
+pub struct Controller {
+    buttons: ButtonState,
+    shift_register: u8,
+    strobe: bool
+}
+
+impl Controller {
+    pub fn new() -> Controller {
+        Default::default()
+    }
+
+    pub fn set_buttons(&mut self, buttons: ButtonState) {
+        self.buttons = buttons;
+        if self.strobe {
+            self.reload();
+        }
+    }
+
+    // Only bit 0 of $4016 is wired to the strobe line
+    pub fn write(&mut self, value: u8) {
+        self.strobe = value & 1 != 0;
+        if self.strobe {
+            self.reload();
+        }
+    }
+
+    // Serial data on bit 0, official pads report 1 after all eight buttons were shifted out
+    pub fn read(&mut self) -> u8 {
+        let value = self.peek();
+        if !self.strobe {
+            self.shift_register = (self.shift_register >> 1) | 0b1000_0000;
+        }
+        value
+    }
+
+    pub fn peek(&self) -> u8 {
+        if self.strobe {
+            self.buttons.contains(ButtonState::A) as u8
+        } else {
+            self.shift_register & 1
+        }
+    }
+
+    fn reload(&mut self) {
+        self.shift_register = self.buttons.bits();
+    }
+}
+
+impl Snapshot for Controller {
+    fn snapshot(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.buttons.bits());
+        writer.write_u8(self.shift_register);
+        writer.write_bool(self.strobe);
+    }
+
+    fn restore(&mut self, reader: &mut StateReader) -> Result<(), String> {
+        self.buttons = ButtonState::from_bits_truncate(reader.read_u8()?);
+        self.shift_register = reader.read_u8()?;
+        self.strobe = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shift_out_buttons() {
+        let mut controller = Controller::new();
+        controller.set_buttons(ButtonState::A | ButtonState::START | ButtonState::RIGHT);
+        controller.write(1);
+        controller.write(0);
+        let reads: Vec<u8> = (0..10).map(|_| controller.read()).collect();
+        assert_eq!(reads, vec![1, 0, 0, 1, 0, 0, 0, 1, 1, 1])
+    }
+
+    #[test]
+    fn test_strobe_high_reports_a() {
+        let mut controller = Controller::new();
+        controller.write(1);
+        controller.set_buttons(ButtonState::A | ButtonState::B);
+        assert_eq!(controller.read(), 1);
+        assert_eq!(controller.read(), 1);
+        controller.write(0);
+        assert_eq!(controller.read(), 1);
+        assert_eq!(controller.read(), 1);
+        assert_eq!(controller.read(), 0)
+    }
+}
@@ -1,42 +1,66 @@
 extern crate winit;
 
-use crate::op_code::OpCode;
+use crate::op_code::{OpCode, OpCodeInfo};
+use crate::op_code::Mnemonic::*;
 use crate::addressing::AddressingMode::{IndexedIndirect, ZeroPage, Immediate, IndirectIndexed, ZeroPageIndexed, Absolute, AbsoluteIndexed, Accumulator, Indirect, Relative, Implied};
-use crate::bus::Bus;
-use crate::addressing::{Addressing, AddressingMode, AddressingRegistry};
+use crate::bus::{Bus, CpuBus};
+use crate::addressing::{Addressing, AddressingRegistry};
 use crate::util::{combine_u8, msb, lsb, nth_bit};
 use crate::flags::Flags;
 
 use std::ops::{BitOr, BitAnd, BitXor, Shl, Shr};
 use log::{info, warn};
 use bitflags::_core::num::Wrapping;
-use std::{u8, fmt};
-use std::borrow::{Borrow, BorrowMut};
+use std::fmt;
 use bitflags::_core::fmt::{Formatter, Error};
-use crate::cartridge::{CartridgeLoader, Cartridge};
 use std::path::Path;
-use std::fs::File;
-use std::fmt::Write as FmtWrite;
-use std::io::Write as IoWrite;
-use std::fmt::UpperHex;
 use crate::screen::Screen;
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use self::winit::event_loop::EventLoop;
+use crate::trace::{Tracer, CpuState};
+use crate::save_state::{slot_path, Snapshot, StateReader, StateWriter};
+use crate::console::Console;
+use std::fs;
+use crate::input::{Hotkey, Input};
+use crate::movie::{apply_frame, MovieCommand, MovieFrame, MovieSession};
+use crate::rewind::Rewind;
+use self::winit::event_loop::{ControlFlow, EventLoop};
 use self::winit::platform::run_return::EventLoopExtRunReturn;
 
-pub struct Cpu {
+// Value ORed into the accumulator by the unstable XAA/LXA opcodes, varies between chips
+static UNSTABLE_CONSTANT: u8 = 0xEE;
+
+static OAM_DATA: u16 = 0x2004;
+
+// The 65C02 is not modelled, its extra opcodes and addressing modes would need a table of their own
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum CpuVariant {
+    // The NES CPU, the decimal flag can be set but the BCD circuitry is disconnected
+    Ricoh2A03,
+    Nmos6502
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+enum Interrupt {
+    Nmi,
+    Irq
+}
+
+pub struct Cpu<B: CpuBus = Bus> {
     stack_pointer: u8,
     program_counter: u16,
     acc: u8,
     reg_x: u8,
     reg_y: u8,
     status: Flags,
-    cycles: u8,
-    bus: Bus,
+    elapsed_cycles: u8,
+    page_crossed: bool,
+    variant: CpuVariant,
+    pending_interrupt: Option<Interrupt>,
+    total_cycles: u64,
+    tracer: Option<Tracer>,
+    bus: B,
 }
 
-impl fmt::Debug for Cpu {
+impl<B: CpuBus> fmt::Debug for Cpu<B> {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         f.debug_struct("Cpu")
             .field("stack_pointer_hex", &format_args!("{:#01X}", self.stack_pointer))
@@ -46,41 +70,123 @@ impl fmt::Debug for Cpu {
             .field("reg_y", &format_args!("{:#01X}", self.reg_y))
             .field("status", &format_args!("{:?}", self.status))
             .field("status", &format_args!("{:#01X}", self.status))
-            .field("cycles", &self.cycles)
+            .field("total_cycles", &self.total_cycles)
             .finish()
     }
 }
 
-impl Cpu {
-    pub fn new(bus: Bus, program_counter: Option<u16>) -> Cpu {
+impl<B: CpuBus> Cpu<B> {
+    pub fn new(bus: B, program_counter: Option<u16>) -> Cpu<B> {
         let mut cpu = Cpu {
             stack_pointer: 0xfd,
             program_counter: 0,
             acc: 0,
             reg_x: 0,
             reg_y: 0,
-            cycles: 0,
+            elapsed_cycles: 0,
+            page_crossed: false,
+            variant: CpuVariant::Ricoh2A03,
+            pending_interrupt: None,
+            total_cycles: 0,
+            tracer: None,
             status: Default::default(),
             bus
         };
-        match program_counter { // TODO: Implement reset vector handling
+        match program_counter {
             Some(x) => cpu.program_counter = x,
-            None          => cpu.reset_vector()
+            None          => cpu.power_on()
         }
         cpu
     }
 
-    pub fn emulation_loop(&mut self, logfile: &File, screen: &mut Screen, mut event_loop: EventLoop<()>) {
-        event_loop.run_return(move |event, _, control_flow| {
-            self.emulate(&logfile);
-            self.bus.emulate(screen);
-        });
+    // Registers come up cleared with the stack pointer at zero, the reset sequence then brings it to $FD
+    pub fn power_on(&mut self) {
+        info!("Powering on CPU");
+        self.acc = 0;
+        self.reg_x = 0;
+        self.reg_y = 0;
+        self.stack_pointer = 0;
+        self.status = Default::default();
+        self.reset();
+    }
+
+    // Runs the interrupt sequence with the stack writes turned into reads, only SP and I change
+    pub fn reset(&mut self) -> u8 {
+        info!("Resetting CPU");
+        self.pending_interrupt = None;
+        self.bus.acknowledge_nmi();
+        self.elapsed_cycles = 0;
+        self.dummy_fetch(self.program_counter);
+        self.dummy_fetch(self.program_counter);
+        for _ in 0..3 {
+            self.dummy_fetch(self.stack_address());
+            self.stack_pointer = (Wrapping(self.stack_pointer) - Wrapping(1)).0;
+        }
+        self.status.insert(Flags::IRQ_DIS);
+        self.program_counter = self.read_vector(0xFFFC);
+        info!("Reset program counter {:01X}", self.program_counter);
+        self.elapsed_cycles
     }
 
-    fn reset_vector(&mut self) {
-        let msb = self.fetch(0xFFFD);
-        let lsb = self.fetch(0xFFFC);
-        self.program_counter = combine_u8(lsb, msb);
+    // The binary always runs a 2A03, other variants are for 6502 builds outside the NES
+    #[allow(dead_code)]
+    pub fn set_variant(&mut self, variant: CpuVariant) {
+        self.variant = variant;
+    }
+
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
+    pub fn tracer_mut(&mut self) -> Option<&mut Tracer> {
+        self.tracer.as_mut()
+    }
+
+    // CPU cycles since power on, the CYC column of the trace
+    pub fn total_cycles(&self) -> u64 {
+        self.total_cycles
+    }
+
+    pub fn state(&self) -> CpuState {
+        CpuState {
+            program_counter: self.program_counter,
+            acc: self.acc,
+            reg_x: self.reg_x,
+            reg_y: self.reg_y,
+            status: self.status,
+            stack_pointer: self.stack_pointer,
+            cycles: self.total_cycles
+        }
+    }
+
+    #[cfg(test)]
+    pub fn load_state(&mut self, state: &CpuState) {
+        self.program_counter = state.program_counter;
+        self.acc = state.acc;
+        self.reg_x = state.reg_x;
+        self.reg_y = state.reg_y;
+        self.status = state.status;
+        self.stack_pointer = state.stack_pointer;
+        self.total_cycles = state.cycles;
+    }
+
+    #[cfg(test)]
+    pub fn set_program_counter(&mut self, program_counter: u16) {
+        self.program_counter = program_counter;
+    }
+
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+
+    pub fn bus_mut(&mut self) -> &mut B {
+        &mut self.bus
+    }
+
+    fn read_vector(&mut self, vector: u16) -> u16 {
+        let lsb = self.fetch(vector);
+        let msb = self.fetch(vector + 1);
+        combine_u8(lsb, msb)
     }
 
     fn set_carry(&mut self, result: u16) {
@@ -108,7 +214,7 @@ impl Cpu {
     }
 
     fn set_overflow(&mut self, lhs: u8, rhs: u8, result: u16, add: bool) {
-        if self.overflow_occurred(lhs, rhs, (result as u8), add) {
+        if self.overflow_occurred(lhs, rhs, result as u8, add) {
             self.status.insert(Flags::OVERFLOW)
         } else {
             self.status.remove(Flags::OVERFLOW)
@@ -126,10 +232,10 @@ impl Cpu {
     fn indexed_indirect_address(&mut self) -> u16 {
         self.program_counter += 1;
         let op_code_arg = self.fetch(self.program_counter);
-        let lsb = self.fetch(((Wrapping(op_code_arg) + Wrapping(self.reg_x)).0 & 0xFF) as u16);
-        let msb = self.fetch(((Wrapping(op_code_arg) + Wrapping(self.reg_x) + Wrapping(1)).0 & 0xFF) as u16);
-        let address = combine_u8(lsb, msb);
-        address
+        self.dummy_fetch(op_code_arg as u16);
+        let lsb = self.fetch((Wrapping(op_code_arg) + Wrapping(self.reg_x)).0 as u16);
+        let msb = self.fetch((Wrapping(op_code_arg) + Wrapping(self.reg_x) + Wrapping(1)).0 as u16);
+        combine_u8(lsb, msb)
     }
 
     fn indirect_indexed_address(&mut self) -> u16 {
@@ -137,15 +243,18 @@ impl Cpu {
         let op_code_arg = self.fetch(self.program_counter);
         let lsb = self.fetch(op_code_arg as u16);
         let msb = self.fetch((Wrapping(op_code_arg) + Wrapping(1)).0 as u16);
-        let address = (Wrapping(combine_u8(lsb, msb)) + Wrapping(self.reg_y as u16)).0 as u16;
+        let base = combine_u8(lsb, msb);
+        let address = (Wrapping(base) + Wrapping(self.reg_y as u16)).0;
+        self.page_crossed = self.is_on_different_page(base, address);
+        if self.page_crossed {
+            self.dummy_fetch(address.wrapping_sub(0x100));
+        }
         address
     }
 
     fn zero_page_address(&mut self) -> u16 {
         self.program_counter += 1;
-        let lsb = self.fetch(self.program_counter);
-        let address = lsb as u16;
-        address
+        self.fetch(self.program_counter) as u16
     }
 
     fn immediate_address(&mut self) -> u16 {
@@ -171,18 +280,24 @@ impl Cpu {
             Some(AddressingRegistry::Y) => self.reg_y,
             _ => panic!("Addressing registry has to be filled")
         };
-        (Wrapping((combine_u8(lsb, msb) as u16)) + Wrapping((to_add as u16))).0
+        let base = combine_u8(lsb, msb);
+        let address = (Wrapping(base) + Wrapping(to_add as u16)).0;
+        self.page_crossed = self.is_on_different_page(base, address);
+        if self.page_crossed {
+            self.dummy_fetch(address.wrapping_sub(0x100));
+        }
+        address
     }
 
     fn zero_page_indexed_address(&mut self, addressing: &Addressing) -> u16 {
         self.program_counter += 1;
         let base = self.fetch(self.program_counter);
+        self.dummy_fetch(base as u16);
         let to_add = match addressing.register {
             Some(AddressingRegistry::X) => self.reg_x,
             Some(AddressingRegistry::Y) => self.reg_y,
             _ => panic!("Addressing registry has to be filled")
         };
-        // TODO: +1 to cycle if wrapped
         info!("base {:#01X} add {:#01X} result {:#01X}", base, to_add, (Wrapping(base) + Wrapping(to_add)).0 as u16);
         (Wrapping(base) + Wrapping(to_add)).0 as u16
     }
@@ -191,7 +306,7 @@ impl Cpu {
         self.indexed_address(addressing)
     }
 
-    fn indirect_address(&mut self, addressing: &Addressing) -> u16 {
+    fn indirect_address(&mut self) -> u16 {
         self.program_counter += 1;
         let lsb = self.fetch(self.program_counter);
         self.program_counter += 1;
@@ -205,7 +320,7 @@ impl Cpu {
         combine_u8(real_lsb, real_msb)
     }
 
-    fn relative(&mut self, addressing: &Addressing) -> u16 {
+    fn relative(&mut self) -> u16 {
         self.program_counter += 1;
         self.program_counter
     }
@@ -234,10 +349,10 @@ impl Cpu {
                 self.absolute_indexed_address(addressing)
             },
             Indirect => {
-                self.indirect_address(addressing)
+                self.indirect_address()
             },
             Relative => {
-                self.relative(addressing)
+                self.relative()
             },
             // TODO: Improve logging
             _ => panic!("Cannot fetch address with given address mode")
@@ -253,25 +368,90 @@ impl Cpu {
         }
     }
 
+    // Indexed writes always spend a cycle reading the address before the page is fixed up
+    fn fetch_write_address(&mut self, addressing: &Addressing) -> u16 {
+        let address = self.fetch_address(addressing);
+        let indexed = addressing.mode == AbsoluteIndexed || addressing.mode == IndirectIndexed;
+        if indexed && !self.page_crossed {
+            self.dummy_fetch(address);
+        }
+        address
+    }
+
+    fn fetch_for_modify(&mut self, addressing: &Addressing) -> (u8, Option<u16>) {
+        if addressing.mode == Accumulator {
+            self.dummy_fetch(self.program_counter.wrapping_add(1));
+            (self.acc, None)
+        } else {
+            let address = self.fetch_write_address(addressing);
+            (self.fetch(address), Some(address))
+        }
+    }
+
     fn fetch(&mut self, address: u16) -> u8 {
-        let value = self.bus.fetch(address);
-        return value
+        self.tick();
+        self.bus.fetch(address)
+    }
+
+    fn dummy_fetch(&mut self, address: u16) {
+        self.fetch(address);
     }
 
     fn store(&mut self, value: u8, address: Option<u16>) {
         match address {
             None => self.acc = value,
-            Some(add) => self.bus.store(value, add)
+            Some(add) => {
+                self.tick();
+                self.bus.store(value, add);
+                if let Some(page) = self.bus.take_oam_dma() {
+                    self.oam_dma(page);
+                }
+            }
         }
     }
 
-    fn push_flags_on_stack(&mut self) {
-        self.push_on_stack(u8::from(self.status));
+    // Read-modify-write instructions write the unmodified value back before the result
+    fn write_back(&mut self, original: u8, result: u8, address: Option<u16>) {
+        if address.is_some() {
+            self.store(original, address);
+        }
+        self.store(result, address);
     }
 
-    fn push_status_on_stack(&mut self) {
-        self.push_program_counter_on_stack();
-        self.push_flags_on_stack();
+    // Every bus access advances the bus by one CPU cycle
+    fn tick(&mut self) {
+        self.elapsed_cycles = self.elapsed_cycles.wrapping_add(1);
+        self.clock_bus();
+    }
+
+    // Cycles the CPU is halted for still clock the bus, they are not part of the instruction
+    fn clock_bus(&mut self) {
+        self.total_cycles = self.total_cycles.wrapping_add(1);
+        self.bus.tick();
+    }
+
+    // A wait cycle, one more to line up with a read cycle when it lands on an odd cycle,
+    // then a read and a write to OAMDATA for each of the 256 bytes, 513 or 514 cycles in total
+    fn oam_dma(&mut self, page: u8) {
+        self.clock_bus();
+        if self.total_cycles % 2 == 1 {
+            self.clock_bus();
+        }
+        for offset in 0..=0xFF {
+            self.clock_bus();
+            let value = self.bus.fetch(combine_u8(offset, page));
+            self.clock_bus();
+            self.bus.store(value, OAM_DATA);
+        }
+    }
+
+    fn stack_address(&self) -> u16 {
+        self.stack_pointer as u16 + 0x100
+    }
+
+    // PHP always pushes B and bit 5 set, interrupts push their own copy
+    fn push_flags_on_stack(&mut self) {
+        self.push_on_stack(u8::from(self.status | Flags::BRK | Flags::PLACEHOLDER));
     }
 
     fn push_program_counter_on_stack(&mut self) {
@@ -280,15 +460,15 @@ impl Cpu {
     }
 
     fn push_on_stack(&mut self, value: u8) {
-        self.store(value, Some((self.stack_pointer as u16) + 0x100));
-        self.stack_pointer -= 1;
+        self.store(value, Some(self.stack_address()));
+        self.stack_pointer = (Wrapping(self.stack_pointer) - Wrapping(1)).0;
     }
 
     fn read_flags_from_stack(&mut self) -> Flags {
         let mut flags: Flags = self.pull_from_stack().into();
         flags.remove(Flags::BRK);
         flags.insert(Flags::PLACEHOLDER);
-        return flags
+        flags
     }
 
     fn read_pc_from_stack(&mut self) -> u16 {
@@ -298,134 +478,185 @@ impl Cpu {
     }
 
     fn pull_from_stack(&mut self) -> u8 {
-        self.stack_pointer += 1;
-        // TODO: Find a fancier way to offset stack pointer
-        self.fetch((self.stack_pointer as u16 + 0x100))
+        self.stack_pointer = (Wrapping(self.stack_pointer) + Wrapping(1)).0;
+        self.fetch(self.stack_address())
     }
 
-    pub fn emulate(&mut self, mut logfile: &File) {
-        if self.cycles != 0 {
-            self.cycles -= 1;
-        } else {
-            if self.bus.nmi {
-                self.cycles += self.nmi_interrupt();
-            } else {
+    pub fn emulate(&mut self) {
+        self.step();
+    }
+
+    // Runs a single instruction or interrupt sequence, returns the number of cycles it took
+    fn step(&mut self) -> u8 {
+        match self.pending_interrupt.take() {
+            Some(Interrupt::Nmi) => {
+                self.bus.acknowledge_nmi();
+                self.nmi_interrupt()
+            },
+            Some(Interrupt::Irq) => self.irq_interrupt(),
+            None => {
+                self.trace();
                 let op_code = self.fetch(self.program_counter);
-                writeln!(
-                    logfile,
-                    // TODO: Fix length, add padding
-                    "{:01X} {} A:{} X:{} Y:{} P:{} SP:{}",
-                    op_code,
-                    self.debug_format(self.program_counter),
-                    self.debug_format(self.acc),
-                    self.debug_format(self.reg_x),
-                    self.debug_format(self.reg_y),
-                    self.debug_format(self.status),
-                    self.debug_format(self.stack_pointer)
-                );
                 info!("cpu before: {:?}", self);
                 let result = self.evaluate(OpCode::new(op_code));
                 info!("cpu after: {:?}\n", self);
-                self.cycles += result;
+                result
             }
         }
     }
 
-    fn debug_format(&mut self, value: impl UpperHex) -> String {
-        let formatted = format!("{:#01X}", value);
-        let stripped = formatted.strip_prefix("0x").unwrap();
-        if stripped.len() == 1 {
-            return String::from("0".to_owned() + stripped)
+    fn trace(&mut self) {
+        let state = self.state();
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.trace(&self.bus, &state);
         }
-        return String::from(stripped)
     }
 
     pub fn evaluate(&mut self, op_code: OpCode) -> u8 {
         info!("Evaluating op code, hex: {:#02X}, bin: {:#08b}", op_code.value, op_code.value);
-        return match op_code.value {
-            0x18 => self.clear_flag(Flags::CARRY),
-            0xD8 => self.clear_flag(Flags::DECIMAL),
-            0x58 => self.clear_flag(Flags::IRQ_DIS),
-            0xB8 => self.clear_flag(Flags::OVERFLOW),
-            0xF8 => self.set_flag(Flags::DECIMAL),
-            0x78 => self.set_flag(Flags::IRQ_DIS),
-            0x38 => self.set_flag(Flags::CARRY),
-            0xEA => self.noop(),
-            0xAA => self.transfer(AddressingRegistry::Acc, AddressingRegistry::X),
-            0xA8 => self.transfer(AddressingRegistry::Acc, AddressingRegistry::Y),
-            0xBA => self.transfer(AddressingRegistry::StackPtr, AddressingRegistry::X),
-            0x8A => self.transfer(AddressingRegistry::X, AddressingRegistry::Acc),
-            0x9A => self.transfer(AddressingRegistry::X, AddressingRegistry::StackPtr),
-            0x98 => self.transfer(AddressingRegistry::Y, AddressingRegistry::Acc),
-            0x00 => self.force_break(),
-            0x08 => self.push_processor_status(),
-            0x28 => self.pull_processor_status(),
-            0x48 => self.push_accumulator(),
-            0x68 => self.pull_accumulator(),
-            0x40 => self.return_from(true),
-            0x60 => self.return_from(false),
-            0xCA => self.offset_register_by_one(Addressing::immediate(Option::from(AddressingRegistry::X)), false),
-            0x88 => self.offset_register_by_one(Addressing::immediate(Option::from(AddressingRegistry::Y)), false),
-            0xC8 => self.offset_register_by_one(Addressing::immediate(Option::from(AddressingRegistry::Y)), true),
-            0xE8 => self.offset_register_by_one(Addressing::immediate(Option::from(AddressingRegistry::X)), true),
-            0x20 => self.jump_to_subroutine(Addressing::absolute()),
-            _ => self.decode_op_code(op_code)
-        }
+        let info = op_code.info();
+        let irq_disabled = self.status.contains(Flags::IRQ_DIS);
+        self.page_crossed = false;
+        // The op code fetch is the first cycle of every instruction
+        self.elapsed_cycles = 1;
+        self.execute(info);
+        // The lines are sampled once the instruction is done rather than on its penultimate cycle, so an
+        // interrupt raised during the last cycle is taken one instruction early. The I flag is taken from
+        // before CLI, SEI and PLP to keep their one instruction delay, the taken branch without a page
+        // cross that delays interrupts by one more instruction is not modelled
+        let polled_irq_disabled = match info.mnemonic {
+            CLI | SEI | PLP => irq_disabled,
+            _ => self.status.contains(Flags::IRQ_DIS)
+        };
+        self.poll_interrupts(polled_irq_disabled);
+        self.elapsed_cycles
+    }
+
+    fn poll_interrupts(&mut self, irq_disabled: bool) {
+        self.pending_interrupt = if self.bus.nmi() {
+            Some(Interrupt::Nmi)
+        } else if self.bus.irq() && !irq_disabled {
+            Some(Interrupt::Irq)
+        } else {
+            None
+        };
     }
 
-    fn decode_op_code(&mut self, op_code: OpCode) -> u8 {
-        let addressing = Addressing::from_op_code(op_code.mid_op_code(), op_code.lower_op_code());
-        return match (op_code.upper_op_code(), op_code.mid_op_code(), op_code.lower_op_code()) {
-            (upper_op_code, 0b100, 0b000) => self.branch(addressing, upper_op_code),
-            (0b000, _, 0b1) => self.bitwise_instruction(addressing, BitOr::bitor, false),
-            (0b001, _, 0b1) => self.bitwise_instruction(addressing, BitAnd::bitand, true),
-            (0b010, _, 0b1) => self.bitwise_instruction(addressing, BitXor::bitxor, true),
-            (0b011, _, 0b1) => self.add_with_carry(addressing),
-            (0b111, _, 0b1) => self.sub_with_borrow(addressing),
-            (0b110, _, 0b1) => self.compare(addressing, self.acc),
-            (0b100, _, 0b1) => self.store_accumulator(addressing),
-            (0b101, _, 0b1) => self.load_accumulator(addressing),
-            (0b000, _, 0b10) => self.shift_left(addressing),
-            (0b001, _, 0b10) => self.rotate_left(addressing),
-            (0b010, _, 0b10) => self.logical_shift_right(addressing),
-            (0b011, _, 0b10) => self.rotate_right(addressing),
-            (0b100, _, 0b10) => self.store_register(addressing, AddressingRegistry::X),
-            (0b101, _, 0b10) => self.load_register(addressing, AddressingRegistry::X),
-            (0b110, _, 0b10) => self.offset_memory_by_one(addressing, false),
-            (0b111, _, 0b10) => self.offset_memory_by_one(addressing, true),
-            (0b001, _, 0b00) => self.bit_test(addressing),
-            (0b010, _, 0b00) => self.jump(Addressing::absolute()),
-            (0b011, _, 0b00) => self.jump(Addressing::indirect()),
-            (0b100, _, 0b00) => self.store_register(addressing, AddressingRegistry::Y),
-            (0b101, _, 0b00) => self.load_register(addressing, AddressingRegistry::Y),
-            (0b110, _, 0b00) => self.compare(addressing, self.reg_y),
-            (0b111, _, 0b00) => self.compare(addressing, self.reg_x),
-            _ => panic!("Unknown op code")
+    fn execute(&mut self, info: &OpCodeInfo) {
+        let addressing = info.addressing;
+        match info.mnemonic {
+            CLC => self.clear_flag(Flags::CARRY),
+            CLD => self.clear_flag(Flags::DECIMAL),
+            CLI => self.clear_flag(Flags::IRQ_DIS),
+            CLV => self.clear_flag(Flags::OVERFLOW),
+            SED => self.set_flag(Flags::DECIMAL),
+            SEI => self.set_flag(Flags::IRQ_DIS),
+            SEC => self.set_flag(Flags::CARRY),
+            NOP => self.noop(addressing),
+            TAX => self.transfer(AddressingRegistry::Acc, AddressingRegistry::X),
+            TAY => self.transfer(AddressingRegistry::Acc, AddressingRegistry::Y),
+            TSX => self.transfer(AddressingRegistry::StackPtr, AddressingRegistry::X),
+            TXA => self.transfer(AddressingRegistry::X, AddressingRegistry::Acc),
+            TXS => self.transfer(AddressingRegistry::X, AddressingRegistry::StackPtr),
+            TYA => self.transfer(AddressingRegistry::Y, AddressingRegistry::Acc),
+            BRK => self.force_break(),
+            PHP => self.push_processor_status(),
+            PLP => self.pull_processor_status(),
+            PHA => self.push_accumulator(),
+            PLA => self.pull_accumulator(),
+            RTI => self.return_from(true),
+            RTS => self.return_from(false),
+            DEX => self.offset_register_by_one(AddressingRegistry::X, false),
+            DEY => self.offset_register_by_one(AddressingRegistry::Y, false),
+            INY => self.offset_register_by_one(AddressingRegistry::Y, true),
+            INX => self.offset_register_by_one(AddressingRegistry::X, true),
+            JSR => self.jump_to_subroutine(),
+            JMP => self.jump(addressing),
+            BPL => self.branch(addressing, Flags::NEGATIVE, false),
+            BMI => self.branch(addressing, Flags::NEGATIVE, true),
+            BVC => self.branch(addressing, Flags::OVERFLOW, false),
+            BVS => self.branch(addressing, Flags::OVERFLOW, true),
+            BCC => self.branch(addressing, Flags::CARRY, false),
+            BCS => self.branch(addressing, Flags::CARRY, true),
+            BNE => self.branch(addressing, Flags::ZERO, false),
+            BEQ => self.branch(addressing, Flags::ZERO, true),
+            ORA => self.bitwise_instruction(addressing, BitOr::bitor),
+            AND => self.bitwise_instruction(addressing, BitAnd::bitand),
+            EOR => self.bitwise_instruction(addressing, BitXor::bitxor),
+            ADC => self.add_with_carry(addressing),
+            SBC => self.sub_with_borrow(addressing),
+            CMP => self.compare(addressing, self.acc),
+            CPX => self.compare(addressing, self.reg_x),
+            CPY => self.compare(addressing, self.reg_y),
+            STA => self.store_accumulator(addressing),
+            LDA => self.load_accumulator(addressing),
+            ASL => self.shift_left(addressing),
+            ROL => self.rotate_left(addressing),
+            LSR => self.logical_shift_right(addressing),
+            ROR => self.rotate_right(addressing),
+            STX => self.store_register(addressing, AddressingRegistry::X),
+            STY => self.store_register(addressing, AddressingRegistry::Y),
+            LDX => self.load_register(addressing, AddressingRegistry::X),
+            LDY => self.load_register(addressing, AddressingRegistry::Y),
+            DEC => self.offset_memory_by_one(addressing, false),
+            INC => self.offset_memory_by_one(addressing, true),
+            BIT => self.bit_test(addressing),
+            JAM => self.jam(),
+            SLO => self.shift_left_or(addressing),
+            RLA => self.rotate_left_and(addressing),
+            SRE => self.shift_right_xor(addressing),
+            RRA => self.rotate_right_add(addressing),
+            SAX => self.store_accumulator_and_x(addressing),
+            LAX => self.load_accumulator_and_x(addressing),
+            DCP => self.decrement_compare(addressing),
+            ISB => self.increment_subtract(addressing),
+            ANC => self.and_carry(addressing),
+            ALR => self.and_shift_right(addressing),
+            ARR => self.and_rotate_right(addressing),
+            XAA => self.and_x_immediate(addressing),
+            LXA => self.load_accumulator_and_x_immediate(addressing),
+            AXS => self.and_x_subtract(addressing),
+            AHX => self.store_high_byte_and(addressing, self.acc & self.reg_x),
+            SHX => self.store_high_byte_and(addressing, self.reg_x),
+            SHY => self.store_high_byte_and(addressing, self.reg_y),
+            TAS => self.transfer_and_store(addressing),
+            LAS => self.load_and_stack(addressing),
         }
     }
 
-    fn noop(&mut self) -> u8 {
-        let cycles = 2;
+    fn implied_cycle(&mut self) {
+        self.dummy_fetch(self.program_counter.wrapping_add(1));
+    }
+
+    fn noop(&mut self, addressing: Addressing) {
+        if addressing.mode == Implied {
+            self.implied_cycle();
+        } else {
+            self.fetch_with_addressing_mode(&addressing);
+        }
         self.program_counter += 1;
-        cycles
     }
 
-    fn clear_flag(&mut self, flag: Flags) -> u8 {
-        let cycles = 2;
+    fn jam(&mut self) {
+        // The processor locks up, keep fetching the same op code until reset
+        warn!("CPU jammed at {:#01X}", self.program_counter);
+        self.implied_cycle();
+    }
+
+    fn clear_flag(&mut self, flag: Flags) {
+        self.implied_cycle();
         self.status.set_flag(false, flag);
         self.program_counter += 1;
-        cycles
     }
 
-    fn set_flag(&mut self, flag: Flags) -> u8 {
-        let cycles = 2;
+    fn set_flag(&mut self, flag: Flags) {
+        self.implied_cycle();
         self.status.set_flag(true, flag);
         self.program_counter += 1;
-        cycles
     }
 
-    fn transfer(&mut self, from: AddressingRegistry, into: AddressingRegistry) -> u8 {
-        let cycles = 2;
+    fn transfer(&mut self, from: AddressingRegistry, into: AddressingRegistry) {
+        self.implied_cycle();
         let from = match from {
             AddressingRegistry::X => self.reg_x,
             AddressingRegistry::Y => self.reg_y,
@@ -443,152 +674,134 @@ impl Cpu {
             self.set_negative(from as u16);
         }
         self.program_counter += 1;
-        cycles
     }
 
     fn nmi_interrupt(&mut self) -> u8 {
         info!("Handling NMI interrupt");
-        let cycles = 2;
+        self.elapsed_cycles = 0;
+        self.dummy_fetch(self.program_counter);
+        self.dummy_fetch(self.program_counter);
+        self.interrupt_sequence(0xFFFA, false);
+        info!("NMI program counter {:01X}", self.program_counter);
+        self.elapsed_cycles
+    }
+
+    fn irq_interrupt(&mut self) -> u8 {
+        info!("Handling IRQ interrupt");
+        self.elapsed_cycles = 0;
+        self.dummy_fetch(self.program_counter);
+        self.dummy_fetch(self.program_counter);
+        self.interrupt_sequence(0xFFFE, false);
+        self.elapsed_cycles
+    }
+
+    // Shared tail of BRK, IRQ and NMI: push the return address and status, then jump through the vector
+    fn interrupt_sequence(&mut self, vector: u16, break_flag: bool) {
         self.push_program_counter_on_stack();
-        self.push_flags_on_stack();
+        let mut flags = self.status | Flags::PLACEHOLDER;
+        flags.set_flag(break_flag, Flags::BRK);
+        self.push_on_stack(flags.bits());
         self.status.insert(Flags::IRQ_DIS);
-        let lsb = self.fetch(0xFFFA);
-        let msb = self.fetch(0xFFFB);
-        self.program_counter = combine_u8(lsb, msb);
-        info!("NMI program counter {:01X}", self.program_counter);
-        cycles
+        // An NMI raised before the vector is fetched hijacks a BRK or IRQ
+        let vector = if vector != 0xFFFA && self.bus.nmi() {
+            info!("NMI hijacked interrupt sequence");
+            self.bus.acknowledge_nmi();
+            0xFFFA
+        } else {
+            vector
+        };
+        self.program_counter = self.read_vector(vector);
     }
 
-    fn push_accumulator(&mut self) -> u8 {
-        let cycles = 3;
+    fn push_accumulator(&mut self) {
+        self.implied_cycle();
         self.push_on_stack(self.acc);
         self.program_counter += 1;
-        cycles
     }
 
-    fn pull_accumulator(&mut self) -> u8 {
-        let cycles = 3;
-        info!("stack: {:01X}", self.fetch(self.stack_pointer as u16));
+    fn pull_accumulator(&mut self) {
+        self.implied_cycle();
+        self.dummy_fetch(self.stack_address());
         self.acc = self.pull_from_stack();
         self.set_zero(self.acc as u16);
         self.set_negative(self.acc as u16);
         self.program_counter += 1;
-        cycles
     }
 
-    fn push_processor_status(&mut self) -> u8 {
-        let cycles = 3;
+    fn push_processor_status(&mut self) {
+        self.implied_cycle();
         self.push_flags_on_stack();
         self.program_counter += 1;
-        cycles
     }
 
-    fn pull_processor_status(&mut self) -> u8 {
-        let cycles = 3;
+    fn pull_processor_status(&mut self) {
+        self.implied_cycle();
+        self.dummy_fetch(self.stack_address());
         self.status = self.read_flags_from_stack();
         self.program_counter += 1;
-        cycles
-    }
-
-    fn branch(&mut self, addressing: Addressing, branch_instruction: u8) -> u8 {
-        let mut cycles = 2;
-        let branch_flag = self.extract_branch_flag(branch_instruction);
-        let branch_equality = self.extract_branch_equality(branch_instruction);
-        let (succeeded, new_page) = match branch_flag {
-            0b00 => self.branch_on_flag(addressing, branch_equality, Flags::NEGATIVE),
-            0b01 => self.branch_on_flag(addressing, branch_equality, Flags::OVERFLOW),
-            0b10 => self.branch_on_flag(addressing, branch_equality, Flags::CARRY),
-            0b11 => self.branch_on_flag(addressing, branch_equality, Flags::ZERO),
-            _ => panic!("Unknown branch type")
-        };
-        if succeeded {
-            cycles += 1;
-        }
-        if new_page {
-            cycles += 1;
-        }
-        self.program_counter += 1;
-        cycles
-    }
-
-    fn extract_branch_flag(&mut self, branch_instruction: u8) -> u8 {
-        let higher_bit = nth_bit(branch_instruction, 2) as u8;
-        let lower_bit = nth_bit(branch_instruction, 1) as u8;
-        ((higher_bit << 1) as u8) | lower_bit
     }
 
-    fn extract_branch_equality(&mut self, branch_instruction: u8) -> bool {
-        nth_bit(branch_instruction, 0)
-    }
-
-    fn branch_on_flag(&mut self, addressing: Addressing, branch_equality: bool, flag: Flags) -> (bool, bool) {
-        let mut succeeded = false;
+    fn branch(&mut self, addressing: Addressing, flag: Flags, branch_equality: bool) {
         let (raw_branch_offset, _) = self.fetch_with_addressing_mode(&addressing);
         let branch_offset = raw_branch_offset as i8;
-        let flag = self.status.contains(flag);
-        if flag == branch_equality {
+        let next_instruction = self.program_counter.wrapping_add(1);
+        if self.status.contains(flag) == branch_equality {
+            self.dummy_fetch(next_instruction);
             self.program_counter = self.program_counter.wrapping_add(branch_offset as u16);
-            succeeded = true;
+            let target = self.program_counter.wrapping_add(1);
+            if self.is_on_different_page(next_instruction, target) {
+                self.dummy_fetch((next_instruction & 0xFF00) | (target & 0x00FF));
+            }
         };
-        (succeeded, self.is_on_different_page(self.program_counter, self.program_counter + 1))
+        self.program_counter = self.program_counter.wrapping_add(1);
     }
 
-    fn bit_test(&mut self, addressing: Addressing) -> u8 {
-        let mut cycles = 3;
+    fn bit_test(&mut self, addressing: Addressing) {
         let (to_test, _) = self.fetch_with_addressing_mode(&addressing);
         info!("to test: {:#01X}", to_test);
         let zero = (to_test & self.acc) == 0;
         let negative = msb(to_test) == 1;
-        let overflow = nth_bit(to_test, 6); // TODO: Check if true
+        let overflow = nth_bit(to_test, 6);
 
         self.status.set_flag(zero, Flags::ZERO);
         self.status.set_flag(negative, Flags::NEGATIVE);
         self.status.set_flag(overflow, Flags::OVERFLOW);
-
-        if addressing.mode == Absolute {
-            cycles += 1;
-        }
         self.program_counter += 1;
-        cycles
     }
 
-    fn jump(&mut self, addressing: Addressing) -> u8 {
-        let mut cycles = 3;
+    fn jump(&mut self, addressing: Addressing) {
         self.program_counter = self.fetch_address(&addressing);
-
-        if addressing.mode == Indirect {
-            cycles += 2;
-        }
-        cycles
     }
 
-    fn jump_to_subroutine(&mut self, addressing: Addressing) -> u8 {
-        let mut cycles = 6;
-        let new_pc = self.fetch_address(&addressing);
-        // TODO: Possible error here?
+    fn jump_to_subroutine(&mut self) {
+        // The high byte is read only after the return address has been pushed
+        self.program_counter += 1;
+        let lsb = self.fetch(self.program_counter);
+        self.dummy_fetch(self.stack_address());
+        self.program_counter += 1;
         self.push_program_counter_on_stack();
-        self.program_counter = new_pc;
-        cycles
+        let msb = self.fetch(self.program_counter);
+        self.program_counter = combine_u8(lsb, msb);
     }
 
-    fn return_from(&mut self, read_flags: bool) -> u8 {
-        let mut cycles = 6;
+    fn return_from(&mut self, read_flags: bool) {
+        self.implied_cycle();
+        self.dummy_fetch(self.stack_address());
         if read_flags {
             self.status = self.read_flags_from_stack();
         }
         let mut new_pc = self.read_pc_from_stack();
         if read_flags {
-            // TODO: Probably error here
-            new_pc -= 1;
+            // RTI restores the exact address, RTS the one before the next instruction
+            new_pc = new_pc.wrapping_sub(1);
+        } else {
+            self.dummy_fetch(new_pc);
         }
-        self.program_counter = new_pc + 1;
-        cycles
+        self.program_counter = new_pc.wrapping_add(1);
     }
 
-    fn store_register(&mut self, addressing: Addressing, target: AddressingRegistry) -> u8 {
-        let mut cycles = 3;
-        let adjusted_addressing = self.adjust_addressing(addressing, target);
-        let address = self.fetch_address(&adjusted_addressing);
+    fn store_register(&mut self, addressing: Addressing, target: AddressingRegistry) {
+        let address = self.fetch_write_address(&addressing);
         let register_value = if target == AddressingRegistry::X {
             self.reg_x
         } else {
@@ -596,20 +809,17 @@ impl Cpu {
         };
         info!("Storing {:#01X} at address  {:#01X}", register_value, address);
         self.store(register_value, Some(address));
-
-        cycles += self.count_additional_cycles(cycles, addressing.add_cycles, false);
         self.program_counter += 1;
-        cycles
     }
 
-    fn offset_register_by_one(&mut self, addressing: Addressing, increment: bool) -> u8 {
-        let mut cycles = 2;
-        match addressing.register {
-            Some(AddressingRegistry::X) => {
+    fn offset_register_by_one(&mut self, register: AddressingRegistry, increment: bool) {
+        self.implied_cycle();
+        match register {
+            AddressingRegistry::X => {
                 let result = self.offset_by_one(self.reg_x, increment);
                 self.reg_x = result;
             },
-            Some(AddressingRegistry::Y) => {
+            AddressingRegistry::Y => {
                 let result = self.offset_by_one(self.reg_y, increment);
                 self.reg_y = result;
             }
@@ -619,18 +829,13 @@ impl Cpu {
             }
         };
         self.program_counter += 1;
-        cycles
     }
 
-    fn offset_memory_by_one(&mut self, addressing: Addressing, increment: bool) -> u8 {
-        let mut cycles = 2;
-        let (mut value, address) = self.fetch_with_addressing_mode(&addressing);
+    fn offset_memory_by_one(&mut self, addressing: Addressing, increment: bool) {
+        let (value, address) = self.fetch_for_modify(&addressing);
         let result = self.offset_by_one(value, increment);
-        self.store(result, address);
-
-        cycles += self.count_additional_cycles(cycles, addressing.add_cycles, false);
+        self.write_back(value, result, address);
         self.program_counter += 1;
-        cycles
     }
 
     fn offset_by_one(&mut self, value: u8,  increment: bool) -> u8 {
@@ -640,30 +845,13 @@ impl Cpu {
         } else {
             result = (Wrapping(result) - Wrapping(1)).0;
         }
-//        self.store(result, address);
         self.set_negative(result as u16);
         self.set_zero(result as u16);
         result
     }
 
-    fn adjust_addressing(&mut self, addressing: Addressing, target: AddressingRegistry) -> Addressing {
-        // TODO: Move to addressing.rs. Use only in STX and LDX
-        if target == AddressingRegistry::X {
-            if addressing.mode == ZeroPageIndexed && addressing.register == Some(AddressingRegistry::X) {
-                return Addressing::zero_page_indexed(Some(AddressingRegistry::Y), false)
-            }
-            if addressing.mode == AbsoluteIndexed && addressing.register == Some(AddressingRegistry::X) {
-                return Addressing::absolute_indexed(Some(AddressingRegistry::Y), false)
-            }
-        }
-        return addressing
-    }
-
-    fn load_register(&mut self, addressing: Addressing, target: AddressingRegistry) -> u8 {
-        info!("cpu before: {:?}", self);
-        let mut cycles = 2;
-        let adjusted_addressing = self.adjust_addressing(addressing, target);
-        let (value, _) = self.fetch_with_addressing_mode(&adjusted_addressing);
+    fn load_register(&mut self, addressing: Addressing, target: AddressingRegistry) {
+        let (value, _) = self.fetch_with_addressing_mode(&addressing);
         self.set_negative(value as u16);
         self.set_zero(value as u16);
         if target == AddressingRegistry::X {
@@ -671,119 +859,98 @@ impl Cpu {
         } else {
             self.reg_y = value;
         }
-        info!("cpu after: {:?}", self);
-        cycles += self.count_additional_cycles(cycles, addressing.add_cycles, true);
         self.program_counter += 1;
-        cycles
     }
 
-    fn shift_left(&mut self, addressing: Addressing) -> u8 {
-        let mut cycles = 2;
-        let (value, address) = self.fetch_with_addressing_mode(&addressing);
-        let (result, carry) = value.overflowing_mul(2);
-        self.store(result, address);
+    fn shift_left(&mut self, addressing: Addressing) {
+        let (value, address) = self.fetch_for_modify(&addressing);
+        let result = self.shift_value_left(value);
+        self.write_back(value, result, address);
         self.set_negative(result as u16);
         self.set_zero(result as u16);
-        self.status.set_flag(carry, Flags::CARRY);
+        self.program_counter += 1;
+    }
 
-        cycles += self.count_additional_cycles(cycles, addressing.add_cycles, false);
+    fn logical_shift_right(&mut self, addressing: Addressing) {
+        let (value, address) = self.fetch_for_modify(&addressing);
+        let result = self.shift_value_right(value);
+        self.write_back(value, result, address);
+        self.set_negative(result as u16);
+        self.set_zero(result as u16);
         self.program_counter += 1;
-        cycles
     }
 
-    fn logical_shift_right(&mut self, addressing: Addressing) -> u8 {
-        let mut cycles = 2;
-        let (value, address) = self.fetch_with_addressing_mode(&addressing);
-        let carry = lsb(value) == 1;
-        let (result, _) = value.overflowing_div(2);
-        self.store(result, address);
+    fn rotate_left(&mut self, addressing: Addressing) {
+        let (value, address) = self.fetch_for_modify(&addressing);
+        let result = self.rotate_value_left(value);
+        self.write_back(value, result, address);
         self.set_negative(result as u16);
         self.set_zero(result as u16);
-        self.status.set_flag(carry, Flags::CARRY);
+        self.program_counter += 1;
+    }
 
-        cycles += self.count_additional_cycles(cycles, addressing.add_cycles, false);
+    fn rotate_right(&mut self, addressing: Addressing) {
+        let (value, address) = self.fetch_for_modify(&addressing);
+        let result = self.rotate_value_right(value);
+        self.write_back(value, result, address);
+        self.set_negative(result as u16);
+        self.set_zero(result as u16);
         self.program_counter += 1;
-        cycles
     }
 
-    fn rotate_left(&mut self, addressing: Addressing) -> u8 {
-        let mut cycles = 2;
-        let (value, address) = self.fetch_with_addressing_mode(&addressing);
+    fn shift_value_left(&mut self, value: u8) -> u8 {
+        let (result, carry) = value.overflowing_mul(2);
+        self.status.set_flag(carry, Flags::CARRY);
+        result
+    }
+
+    fn shift_value_right(&mut self, value: u8) -> u8 {
+        let carry = lsb(value) == 1;
+        let (result, _) = value.overflowing_div(2);
+        self.status.set_flag(carry, Flags::CARRY);
+        result
+    }
+
+    fn rotate_value_left(&mut self, value: u8) -> u8 {
         let carry = msb(value) == 1;
         let mut result = value.shl(1);
         if self.status.contains(Flags::CARRY) {
-            result = result | Flags::CARRY.bits();
+            result |= Flags::CARRY.bits();
         }
-        self.store(result, address);
-        self.set_rotation_flags(result, carry);
-
-        cycles += self.count_additional_cycles(cycles, addressing.add_cycles, false);
-        self.program_counter += 1;
-        cycles
+        self.status.set_flag(carry, Flags::CARRY);
+        result
     }
 
-    fn rotate_right(&mut self, addressing: Addressing) -> u8 {
-        let mut cycles = 2;
-        let (value, address) = self.fetch_with_addressing_mode(&addressing);
+    fn rotate_value_right(&mut self, value: u8) -> u8 {
         let carry = lsb(value) == 1;
         let mut result = value.shr(1);
         if self.status.contains(Flags::CARRY) {
-            result = result | (Flags::CARRY.bits() << 7);
+            result |= Flags::CARRY.bits() << 7;
         }
-        self.store(result, address);
-        self.set_rotation_flags(result, carry);
-
-        cycles += self.count_additional_cycles(cycles, addressing.add_cycles, false);
-        self.program_counter += 1;
-        cycles
-    }
-
-    fn set_rotation_flags(&mut self, result: u8, carry: bool) {
-        self.set_negative(result as u16);
-        self.set_zero(result as u16);
         self.status.set_flag(carry, Flags::CARRY);
+        result
     }
 
-    fn force_break(&mut self) -> u8 {
-        let cycles = 7;
-        self.program_counter += 1;
-        self.status.set_flag(true, Flags::BRK);
-        self.status.set_flag(true, Flags::IRQ_DIS);
-        self.push_status_on_stack();
-
-        let msb = self.fetch(0xFFFE);
-        let lsb = self.fetch(0xFFFF);
-        self.program_counter = combine_u8(lsb, msb);
-        self.status.set_flag(false, Flags::BRK);
-        self.program_counter += 1;
-        cycles
+    fn force_break(&mut self) {
+        // BRK skips a padding byte, the return address points past it
+        self.implied_cycle();
+        self.program_counter = self.program_counter.wrapping_add(2);
+        self.interrupt_sequence(0xFFFE, true);
     }
 
-    fn store_accumulator(&mut self, addressing: Addressing) -> u8 {
-        let mut cycles = 2;
-        let address = self.fetch_address(&addressing);
+    fn store_accumulator(&mut self, addressing: Addressing) {
+        let address = self.fetch_write_address(&addressing);
         self.store(self.acc, Some(address));
-        cycles += self.count_additional_cycles(cycles, addressing.add_cycles, false);
         self.program_counter += 1;
-        cycles
     }
 
-    fn load_accumulator(&mut self, addressing: Addressing) -> u8 {
-        let mut cycles = 2;
+    fn load_accumulator(&mut self, addressing: Addressing) {
         let (value, address) = self.fetch_with_addressing_mode(&addressing);
         info!("Loading {:#01X} from {:#01X}", value, address.unwrap());
         self.set_negative(value as u16);
         self.set_zero(value as u16);
         self.acc = value;
-        cycles += self.count_additional_cycles(cycles, addressing.add_cycles, true);
         self.program_counter += 1;
-        cycles
-    }
-
-    fn carry_arithmetic(&mut self, operation: fn(u8, u8) -> (u8, bool), lhs: u8, rhs: u8) -> (u8, bool) {
-        let (intermediate_result, carry_overflow) = operation(lhs, rhs);
-        let (result, overflow) = operation(intermediate_result, self.status.contains(Flags::CARRY) as u8);
-        (result, overflow | carry_overflow)
     }
 
     fn overflow_occurred(&self, lhs: u8, rhs: u8, result: u8, add: bool) -> bool {
@@ -794,88 +961,404 @@ impl Cpu {
         }
     }
 
-    fn compare(&mut self, addressing: Addressing, target: u8) -> u8 {
-        let mut cycles = 2;
+    fn compare(&mut self, addressing: Addressing, target: u8) {
         let (value, _) = self.fetch_with_addressing_mode(&addressing);
-        let mut result = (Wrapping(target) - Wrapping(value)).0;
+        self.compare_values(target, value);
+        self.program_counter += 1;
+    }
+
+    fn compare_values(&mut self, target: u8, value: u8) {
+        let result = (Wrapping(target) - Wrapping(value)).0;
         let carry = value <= target;
         let zero = result == 0;
         let negative = msb(result) == 1;
         self.status.set_flag(negative, Flags::NEGATIVE);
         self.status.set_flag(carry, Flags::CARRY);
         self.status.set_flag(zero, Flags::ZERO);
+    }
 
-        cycles += self.count_additional_cycles(cycles, addressing.add_cycles, false);
+    fn add_with_carry(&mut self, addressing: Addressing) {
+        let (value, _) = self.fetch_with_addressing_mode(&addressing);
+        self.add_to_accumulator(value);
         self.program_counter += 1;
-        cycles
     }
 
-    fn add_with_carry(&mut self, addressing: Addressing) -> u8 {
-        let mut cycles = 2;
-        let (value, _) = self.fetch_with_addressing_mode(&addressing);
-        let mut result = (self.acc as u16) + (value as u16) + (self.status.contains(Flags::CARRY) as u16);
+    fn decimal_mode(&self) -> bool {
+        self.variant == CpuVariant::Nmos6502 && self.status.contains(Flags::DECIMAL)
+    }
+
+    fn add_to_accumulator(&mut self, value: u8) {
+        if self.decimal_mode() {
+            return self.add_decimal(value)
+        }
+        let result = (self.acc as u16) + (value as u16) + (self.status.contains(Flags::CARRY) as u16);
         self.set_carry(result);
         self.set_zero(result % 256);
         self.set_negative(result);
         self.set_overflow(self.acc, value, result, true);
         self.acc = (result % 256) as u8;
-        cycles += self.count_additional_cycles(cycles, addressing.add_cycles, true);
-        self.program_counter += 1;
-        cycles
     }
 
-    fn sub_with_borrow(&mut self, addressing: Addressing) -> u8 {
-        let mut cycles = 2;
+    fn sub_with_borrow(&mut self, addressing: Addressing) {
         let (value, _) = self.fetch_with_addressing_mode(&addressing);
+        self.subtract_from_accumulator(value);
+        self.program_counter += 1;
+    }
+
+    // NMOS BCD addition: Z comes from the binary sum, N and V from the sum before the high digit is adjusted
+    fn add_decimal(&mut self, value: u8) {
+        let carry = self.status.contains(Flags::CARRY) as u16;
+        let binary = (self.acc as u16) + (value as u16) + carry;
+        let mut low = (self.acc as u16 & 0x0F) + (value as u16 & 0x0F) + carry;
+        if low >= 0x0A {
+            low = ((low + 0x06) & 0x0F) + 0x10;
+        }
+        let mut result = (self.acc as u16 & 0xF0) + (value as u16 & 0xF0) + low;
+        self.set_zero(binary % 256);
+        self.set_negative(result);
+        self.set_overflow(self.acc, value, result, true);
+        if result >= 0xA0 {
+            result += 0x60;
+        }
+        self.set_carry(result);
+        self.acc = (result % 256) as u8;
+    }
+
+    fn subtract_from_accumulator(&mut self, value: u8) {
+        let decimal = self.decimal_mode();
         let carry = if self.status.contains(Flags::CARRY) { 0 } else { 1 };
-        let mut result = (Wrapping(self.acc as u16) - (Wrapping(value as u16)) - Wrapping(carry));
+        let result = Wrapping(self.acc as u16) - Wrapping(value as u16) - Wrapping(carry);
         self.set_borrow(result.0);
         self.set_zero(result.0 % 256);
         self.set_negative(result.0 % 256);
         self.set_overflow(self.acc, value, result.0, false);
+        self.acc = if decimal {
+            self.subtract_decimal(value, 1 - carry as i16)
+        } else {
+            (result.0 % 256) as u8
+        };
+    }
 
-        self.acc = (result.0 % 256) as u8;
-        cycles += self.count_additional_cycles(cycles, addressing.add_cycles, true);
-        self.program_counter += 1;
-        cycles
+    // NMOS BCD subtraction only adjusts the result, every flag comes from the binary subtraction
+    fn subtract_decimal(&self, value: u8, carry: i16) -> u8 {
+        let mut low = (self.acc as i16 & 0x0F) - (value as i16 & 0x0F) + carry - 1;
+        if low < 0 {
+            low = ((low - 0x06) & 0x0F) - 0x10;
+        }
+        let mut result = (self.acc as i16 & 0xF0) - (value as i16 & 0xF0) + low;
+        if result < 0 {
+            result -= 0x60;
+        }
+        result as u8
     }
 
-    fn bitwise_instruction(&mut self, addressing: Addressing, operation: fn(u8, u8) -> u8, additional_cycle: bool) -> u8 {
-        let mut cycles = 2;
+    fn bitwise_instruction(&mut self, addressing: Addressing, operation: fn(u8, u8) -> u8) {
         let (value, _) = self.fetch_with_addressing_mode(&addressing);
         self.acc = operation(self.acc, value);
-        cycles += self.count_additional_cycles(cycles, addressing.add_cycles, additional_cycle);
+        self.set_zero(self.acc as u16);
+        self.set_negative(self.acc as u16);
+        self.program_counter += 1;
+    }
 
+    fn shift_left_or(&mut self, addressing: Addressing) {
+        let (value, address) = self.fetch_for_modify(&addressing);
+        let result = self.shift_value_left(value);
+        self.write_back(value, result, address);
+        self.acc |= result;
         self.set_zero(self.acc as u16);
         self.set_negative(self.acc as u16);
         self.program_counter += 1;
-        cycles
     }
 
-    fn count_additional_cycles(&mut self, mut cycles: u8, add_cycles: bool, additional_cycle: bool) -> u8 {
-        if (self.page_boundary_crossed(self.acc)) & additional_cycle {
-            cycles += 1;
-        }
-        if (add_cycles) & (self.page_boundary_crossed(self.acc)) {
-            cycles += 1;
-        }
-        cycles
+    fn rotate_left_and(&mut self, addressing: Addressing) {
+        let (value, address) = self.fetch_for_modify(&addressing);
+        let result = self.rotate_value_left(value);
+        self.write_back(value, result, address);
+        self.acc &= result;
+        self.set_zero(self.acc as u16);
+        self.set_negative(self.acc as u16);
+        self.program_counter += 1;
+    }
+
+    fn shift_right_xor(&mut self, addressing: Addressing) {
+        let (value, address) = self.fetch_for_modify(&addressing);
+        let result = self.shift_value_right(value);
+        self.write_back(value, result, address);
+        self.acc ^= result;
+        self.set_zero(self.acc as u16);
+        self.set_negative(self.acc as u16);
+        self.program_counter += 1;
+    }
+
+    fn rotate_right_add(&mut self, addressing: Addressing) {
+        let (value, address) = self.fetch_for_modify(&addressing);
+        let result = self.rotate_value_right(value);
+        self.write_back(value, result, address);
+        self.add_to_accumulator(result);
+        self.program_counter += 1;
+    }
+
+    fn decrement_compare(&mut self, addressing: Addressing) {
+        let (value, address) = self.fetch_for_modify(&addressing);
+        let result = (Wrapping(value) - Wrapping(1)).0;
+        self.write_back(value, result, address);
+        self.compare_values(self.acc, result);
+        self.program_counter += 1;
+    }
+
+    fn increment_subtract(&mut self, addressing: Addressing) {
+        let (value, address) = self.fetch_for_modify(&addressing);
+        let result = (Wrapping(value) + Wrapping(1)).0;
+        self.write_back(value, result, address);
+        self.subtract_from_accumulator(result);
+        self.program_counter += 1;
+    }
+
+    fn store_accumulator_and_x(&mut self, addressing: Addressing) {
+        let address = self.fetch_write_address(&addressing);
+        self.store(self.acc & self.reg_x, Some(address));
+        self.program_counter += 1;
+    }
+
+    fn load_accumulator_and_x(&mut self, addressing: Addressing) {
+        let (value, _) = self.fetch_with_addressing_mode(&addressing);
+        self.acc = value;
+        self.reg_x = value;
+        self.set_zero(value as u16);
+        self.set_negative(value as u16);
+        self.program_counter += 1;
+    }
+
+    fn and_carry(&mut self, addressing: Addressing) {
+        let (value, _) = self.fetch_with_addressing_mode(&addressing);
+        self.acc &= value;
+        self.set_zero(self.acc as u16);
+        self.set_negative(self.acc as u16);
+        self.status.set_flag(msb(self.acc) == 1, Flags::CARRY);
+        self.program_counter += 1;
+    }
+
+    fn and_shift_right(&mut self, addressing: Addressing) {
+        let (value, _) = self.fetch_with_addressing_mode(&addressing);
+        self.acc = self.shift_value_right(self.acc & value);
+        self.set_zero(self.acc as u16);
+        self.set_negative(self.acc as u16);
+        self.program_counter += 1;
+    }
+
+    fn and_rotate_right(&mut self, addressing: Addressing) {
+        let (value, _) = self.fetch_with_addressing_mode(&addressing);
+        self.acc = self.rotate_value_right(self.acc & value);
+        let bit_six = nth_bit(self.acc, 6);
+        let bit_five = nth_bit(self.acc, 5);
+        self.set_zero(self.acc as u16);
+        self.set_negative(self.acc as u16);
+        self.status.set_flag(bit_six, Flags::CARRY);
+        self.status.set_flag(bit_six ^ bit_five, Flags::OVERFLOW);
+        self.program_counter += 1;
+    }
+
+    fn and_x_immediate(&mut self, addressing: Addressing) {
+        let (value, _) = self.fetch_with_addressing_mode(&addressing);
+        self.acc = (self.acc | UNSTABLE_CONSTANT) & self.reg_x & value;
+        self.set_zero(self.acc as u16);
+        self.set_negative(self.acc as u16);
+        self.program_counter += 1;
+    }
+
+    fn load_accumulator_and_x_immediate(&mut self, addressing: Addressing) {
+        let (value, _) = self.fetch_with_addressing_mode(&addressing);
+        self.acc = (self.acc | UNSTABLE_CONSTANT) & value;
+        self.reg_x = self.acc;
+        self.set_zero(self.acc as u16);
+        self.set_negative(self.acc as u16);
+        self.program_counter += 1;
+    }
+
+    fn and_x_subtract(&mut self, addressing: Addressing) {
+        let (value, _) = self.fetch_with_addressing_mode(&addressing);
+        let target = self.acc & self.reg_x;
+        self.compare_values(target, value);
+        self.reg_x = (Wrapping(target) - Wrapping(value)).0;
+        self.program_counter += 1;
+    }
+
+    fn store_high_byte_and(&mut self, addressing: Addressing, value: u8) {
+        let address = self.fetch_write_address(&addressing);
+        let base = address.wrapping_sub(self.index_register(&addressing) as u16);
+        let result = value & (Wrapping((base >> 8) as u8) + Wrapping(1)).0;
+        // When the index crosses a page the unstable result also replaces the high address byte
+        let target = if self.page_crossed {
+            combine_u8(address as u8, result)
+        } else {
+            address
+        };
+        self.store(result, Some(target));
+        self.program_counter += 1;
+    }
+
+    fn transfer_and_store(&mut self, addressing: Addressing) {
+        self.stack_pointer = self.acc & self.reg_x;
+        self.store_high_byte_and(addressing, self.stack_pointer)
+    }
+
+    fn load_and_stack(&mut self, addressing: Addressing) {
+        let (value, _) = self.fetch_with_addressing_mode(&addressing);
+        let result = value & self.stack_pointer;
+        self.acc = result;
+        self.reg_x = result;
+        self.stack_pointer = result;
+        self.set_zero(result as u16);
+        self.set_negative(result as u16);
+        self.program_counter += 1;
     }
 
-    fn page_boundary_crossed(&self, value: u8) -> bool {
-        value > 0x00FF
+    fn index_register(&self, addressing: &Addressing) -> u8 {
+        match (addressing.mode, addressing.register) {
+            (IndirectIndexed, _) => self.reg_y,
+            (_, Some(AddressingRegistry::X)) => self.reg_x,
+            (_, Some(AddressingRegistry::Y)) => self.reg_y,
+            _ => 0
+        }
     }
 
     fn is_on_different_page(&self, lhs: u16, rhs: u16) -> bool {
-        let lhs_page = lhs % 255;
-        let rhs_page = rhs % 255;
-        lhs == rhs
+        (lhs & 0xFF00) != (rhs & 0xFF00)
+    }
+}
+
+impl Cpu<Bus> {
+    // Input is latched on frame boundaries so a recorded movie replays exactly
+    pub fn emulation_loop(&mut self, screen: &mut Screen, input: &mut Input, mut movie: Option<MovieSession>,
+                          mut rewind: Rewind, cartridge_path: &Path, mut event_loop: EventLoop<()>) {
+        let mut paused = false;
+        let mut slot = 0;
+        let mut live = MovieFrame::default();
+        let mut frame = self.bus.frame();
+        let first_frame = frame;
+        self.next_movie_frame(&mut movie, &mut live);
+        event_loop.run_return(move |event, _, control_flow| {
+            if input.update(&event) {
+                for hotkey in input.hotkeys() {
+                    match hotkey {
+                        Hotkey::Pause => paused = !paused,
+                        Hotkey::Reset => live.commands.insert(MovieCommand::SOFT_RESET),
+                        Hotkey::SelectSlot(selected) => {
+                            info!("Save state slot {}", selected);
+                            slot = selected;
+                        },
+                        Hotkey::SaveState => {
+                            let path = slot_path(cartridge_path, slot);
+                            if let Err(error) = fs::write(&path, Console::save_state(self)) {
+                                warn!("Could not write save state {}: {}", path.display(), error);
+                            }
+                        },
+                        Hotkey::LoadState => {
+                            let path = slot_path(cartridge_path, slot);
+                            let loaded = fs::read(&path).map_err(|error| error.to_string())
+                                .and_then(|state| Console::load_state(self, &state));
+                            if let Err(error) = loaded {
+                                warn!("Could not load save state {}: {}", path.display(), error);
+                            }
+                        },
+                        Hotkey::Trace => match self.tracer_mut() {
+                            Some(tracer) => {
+                                let enabled = !tracer.is_enabled();
+                                info!("Tracing {}", if enabled { "resumed" } else { "paused" });
+                                tracer.set_enabled(enabled);
+                            },
+                            None => warn!("Tracing needs a trace file, start with --trace trace.log")
+                        },
+                        Hotkey::Rewind => {},
+                        Hotkey::Quit => {
+                            if let Some(session) = movie.as_ref() {
+                                session.finish();
+                            }
+                            *control_flow = ControlFlow::Exit;
+                            return
+                        }
+                    }
+                }
+                for port in 0..2 {
+                    live.ports[port] = input.buttons(port);
+                }
+            }
+            if paused {
+                return
+            }
+            self.emulate();
+            if self.bus.frame() != frame {
+                frame = self.bus.frame();
+                screen.draw_frame(self.bus.frame_buffer());
+                // Rewinding steps back one state per frame and takes the movie back to the restored frame
+                if input.rewinding() {
+                    let state = match rewind.pop() {
+                        Some(state) => state,
+                        None => return
+                    };
+                    if let Err(error) = Console::load_state(self, &state) {
+                        warn!("Could not rewind: {}", error);
+                    }
+                    frame = self.bus.frame();
+                    if let Some(session) = movie.as_mut() {
+                        session.rewind_to(frame.saturating_sub(first_frame) as usize);
+                    }
+                } else if rewind.is_due(frame) {
+                    rewind.push(Console::save_state(self));
+                }
+                self.next_movie_frame(&mut movie, &mut live);
+            }
+        });
     }
 
-    fn get_borrow(&mut self) -> u16 {
-        (self.status.contains(Flags::CARRY) == false) as u16
+    fn next_movie_frame(&mut self, movie: &mut Option<MovieSession>, live: &mut MovieFrame) {
+        let next = match movie.as_mut() {
+            Some(session) => session.next_frame(*live),
+            None => *live
+        };
+        apply_frame(self, &next);
+        live.commands = MovieCommand::empty();
     }
+}
 
+// The variant and the tracer are settings and stay as they are
+impl<B: CpuBus + Snapshot> Snapshot for Cpu<B> {
+    fn snapshot(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.stack_pointer);
+        writer.write_u16(self.program_counter);
+        writer.write_u8(self.acc);
+        writer.write_u8(self.reg_x);
+        writer.write_u8(self.reg_y);
+        writer.write_u8(self.status.bits());
+        writer.write_u8(self.elapsed_cycles);
+        writer.write_bool(self.page_crossed);
+        writer.write_u8(match self.pending_interrupt {
+            None => 0,
+            Some(Interrupt::Nmi) => 1,
+            Some(Interrupt::Irq) => 2
+        });
+        writer.write_u64(self.total_cycles);
+        self.bus.snapshot(writer);
+    }
+
+    fn restore(&mut self, reader: &mut StateReader) -> Result<(), String> {
+        self.stack_pointer = reader.read_u8()?;
+        self.program_counter = reader.read_u16()?;
+        self.acc = reader.read_u8()?;
+        self.reg_x = reader.read_u8()?;
+        self.reg_y = reader.read_u8()?;
+        self.status = Flags::from(reader.read_u8()?);
+        self.elapsed_cycles = reader.read_u8()?;
+        self.page_crossed = reader.read_bool()?;
+        self.pending_interrupt = match reader.read_u8()? {
+            0 => None,
+            1 => Some(Interrupt::Nmi),
+            2 => Some(Interrupt::Irq),
+            other => return Err(format!("unknown pending interrupt {}", other))
+        };
+        self.total_cycles = reader.read_u64()?;
+        self.bus.restore(reader)
+    }
 }
 
 #[cfg(test)]
@@ -884,20 +1367,40 @@ mod tests {
     use super::*;
     use crate::ppu::{Ppu, NameTableMirroring};
     use crate::cartridge::Cartridge;
+    use crate::bus::IrqSource;
+    use crate::flat_bus::FlatBus;
+    use crate::assembler::assemble;
+
+    // Starts at $0000 with an empty stack and only the unused status bit set, so flag checks see
+    // exactly what the instruction changed
+    fn create_test_cpu(input: Vec<u8>) -> Cpu<FlatBus> {
+        let mut cpu = Cpu::new(FlatBus::with_program(&input, 0), Some(0));
+        cpu.status = Flags::PLACEHOLDER;
+        cpu.stack_pointer = 0xFF;
+        cpu
+    }
 
-    fn create_test_bus(input: Vec<u8>) -> Bus {
-        let ppu = Ppu::new(vec![], NameTableMirroring::VERTICAL);
-        let cartridge = Cartridge::new();
-        return Bus::new(input, ppu, cartridge);
+    // Byte at an offset above the stack pointer, the stack lives in page $01
+    fn stack_entry(cpu: &mut Cpu<FlatBus>, offset: u8) -> u8 {
+        cpu.fetch(0x0100 + cpu.stack_pointer as u16 + offset as u16)
     }
 
-    fn create_test_cpu(input: Vec<u8>) -> Cpu {
-        let mut bus = create_test_bus(input);
-        Cpu::new(bus, None)
+    // Powers on with the NMI, reset and IRQ vectors at the top of flat memory
+    fn create_test_cpu_with_vectors(nmi: u16, reset: u16, irq: u16) -> Cpu<FlatBus> {
+        let mut vectors = vec![];
+        for vector in [nmi, reset, irq].iter() {
+            vectors.push(*vector as u8);
+            vectors.push((*vector >> 8) as u8);
+        }
+        Cpu::new(FlatBus::with_program(&vectors, 0xFFFA), None)
+    }
+
+    // Program assembled at $0000, where the test cpu starts
+    fn create_test_cpu_from_source(source: &str) -> Cpu<FlatBus> {
+        create_test_cpu(assemble(source, 0x0000).unwrap())
     }
 
-    fn reset_cpu(cpu: &mut Cpu) {
-        cpu.cycles = 0;
+    fn reset_cpu(cpu: &mut Cpu<FlatBus>) {
         cpu.acc = 0;
         cpu.reg_x = 0;
         cpu.reg_y = 0;
@@ -947,6 +1450,7 @@ mod tests {
         let mut cpu = create_test_cpu(vec![0xE1, 0x02, 0x04, 0x00, 2]);
         reset_cpu(&mut cpu);
         cpu.acc = 3;
+        cpu.status = Flags::PLACEHOLDER | Flags::CARRY;
         cpu.evaluate(OpCode::new(0xE1));
         assert_eq!(cpu.acc, 1);
         assert_eq!(cpu.status, Flags::PLACEHOLDER | Flags::CARRY)
@@ -1174,7 +1678,7 @@ mod tests {
     fn test_bit_test() {
         let mut cpu = create_test_cpu(vec![0x2C, 0x03, 0x00, 0b1100_0000]);
         reset_cpu(&mut cpu);
-        cpu.acc == 0;
+        cpu.acc = 0;
         cpu.evaluate(OpCode::new(0x2C));
         assert_eq!(cpu.status, Flags::NEGATIVE | Flags::PLACEHOLDER | Flags::OVERFLOW | Flags::ZERO)
     }
@@ -1201,52 +1705,54 @@ mod tests {
         reset_cpu(&mut cpu);
         cpu.status = Flags::PLACEHOLDER | Flags::NEGATIVE;
         cpu.evaluate(OpCode::new(0x30));
-        assert_eq!(cpu.program_counter, 5)
+        // Relative to the address after the operand
+        assert_eq!(cpu.program_counter, 6)
     }
 
     #[test]
     fn test_break() {
         let len = 0x10000;
         let mut memory = vec![0; len];
-        // TODO: Fix off by one in tests
         memory[0xFFFE] = 0x44;
         memory[0xFFFF] = 0x66;
         let mut cpu = create_test_cpu(memory);
         reset_cpu(&mut cpu);
         cpu.program_counter = 0x1234;
         cpu.evaluate(OpCode::new(0x00));
-        assert_eq!(cpu.program_counter, 0x4466);
+        assert_eq!(cpu.program_counter, 0x6644);
         assert_eq!(cpu.status, Flags::PLACEHOLDER | Flags::IRQ_DIS);
 
-        let stored_flags: Flags = cpu.fetch((cpu.stack_pointer + 1) as u16).into();
+        // Pushed before the interrupt disable flag is set
+        let stored_flags: Flags = stack_entry(&mut cpu, 1).into();
         assert_eq!(
             stored_flags,
-            Flags::IRQ_DIS | Flags::BRK | Flags::PLACEHOLDER
+            Flags::BRK | Flags::PLACEHOLDER
         );
 
-        let lsb_stored_program_counter =  cpu.fetch((cpu.stack_pointer + 2) as u16);
-        let msb_stored_program_counter =  cpu.fetch((cpu.stack_pointer + 3) as u16);
-        assert_eq!(combine_u8(lsb_stored_program_counter, msb_stored_program_counter), 0x1235)
+        let lsb_stored_program_counter = stack_entry(&mut cpu, 2);
+        let msb_stored_program_counter = stack_entry(&mut cpu, 3);
+        assert_eq!(combine_u8(lsb_stored_program_counter, msb_stored_program_counter), 0x1236)
     }
 
     #[test]
     fn test_jsr() {
         let len = 0xFFFF;
         let mut memory = vec![0; len];
-        memory[0] = 0x60;
-        memory[1] = 0x20;
-        memory[3] = 0x03;
+        memory[0x10] = 0x20;
+        memory[0x11] = 0x34;
+        memory[0x12] = 0x12;
 
         let mut cpu = create_test_cpu(memory);
         reset_cpu(&mut cpu);
-        cpu.program_counter = 2;
+        cpu.program_counter = 0x10;
         cpu.evaluate(OpCode::new(0x20));
-        assert_eq!(cpu.program_counter, 0x03);
+        assert_eq!(cpu.program_counter, 0x1234);
 
-        let lsb_stored_program_counter =  cpu.fetch((cpu.stack_pointer + 1) as u16);
-        let msb_stored_program_counter =  cpu.fetch((cpu.stack_pointer + 2) as u16);
+        // The address of the last operand byte, RTS adds one
+        let lsb_stored_program_counter = stack_entry(&mut cpu, 1);
+        let msb_stored_program_counter = stack_entry(&mut cpu, 2);
 
-        assert_eq!(combine_u8(lsb_stored_program_counter, msb_stored_program_counter), 1)
+        assert_eq!(combine_u8(lsb_stored_program_counter, msb_stored_program_counter), 0x12)
     }
 
     #[test]
@@ -1255,9 +1761,9 @@ mod tests {
         let mut memory = vec![0; len];
         let flags_on_stack = Flags::NEGATIVE | Flags::PLACEHOLDER | Flags::OVERFLOW;
         memory[0] = 0x40;
-        memory[0x00FF] = 0x44;
-        memory[0x00FE] = 0x66;
-        memory[0x00FD] = flags_on_stack.bits();
+        memory[0x01FF] = 0x44;
+        memory[0x01FE] = 0x66;
+        memory[0x01FD] = flags_on_stack.bits();
 
         let mut cpu = create_test_cpu(memory);
         reset_cpu(&mut cpu);
@@ -1273,10 +1779,9 @@ mod tests {
     fn test_rts() {
         let len = 0x10000;
         let mut memory = vec![0; len];
-        let flags_on_stack = Flags::NEGATIVE | Flags::PLACEHOLDER | Flags::OVERFLOW;
-        memory[0] = 0x40;
-        memory[0x00FF] = 0x44;
-        memory[0x00FE] = 0x66;
+        memory[0] = 0x60;
+        memory[0x01FF] = 0x44;
+        memory[0x01FE] = 0x66;
 
         let mut cpu = create_test_cpu(memory);
         reset_cpu(&mut cpu);
@@ -1284,13 +1789,13 @@ mod tests {
         cpu.program_counter = 2;
 
         cpu.evaluate(OpCode::new(0x60));
-        assert_eq!(cpu.program_counter, 0x4465);
+        assert_eq!(cpu.program_counter, 0x4467);
     }
 
     #[test]
     fn test_php() {
         let len = 0x10000;
-        let mut memory = vec![0; len];
+        let memory = vec![0; len];
 
         let mut cpu = create_test_cpu(memory);
         reset_cpu(&mut cpu);
@@ -1298,8 +1803,8 @@ mod tests {
         cpu.status = current_flags;
         cpu.evaluate(OpCode::new(0x08));
 
-        let stored_flags: Flags = cpu.fetch((cpu.stack_pointer + 1) as u16).into();
-        assert_eq!(stored_flags, current_flags)
+        let stored_flags: Flags = stack_entry(&mut cpu, 1).into();
+        assert_eq!(stored_flags, current_flags | Flags::BRK)
     }
 
     #[test]
@@ -1307,7 +1812,7 @@ mod tests {
         let len = 0x10000;
         let mut memory = vec![0; len];
         let stored_flags = Flags::NEGATIVE | Flags::PLACEHOLDER | Flags::OVERFLOW;
-        memory[0x00FF] = stored_flags.bits();
+        memory[0x01FF] = stored_flags.bits();
 
         let mut cpu = create_test_cpu(memory);
         reset_cpu(&mut cpu);
@@ -1321,13 +1826,13 @@ mod tests {
     #[test]
     fn test_push_acc() {
         let len = 0x10000;
-        let mut memory = vec![0; len];
+        let memory = vec![0; len];
         let mut cpu = create_test_cpu(memory);
         reset_cpu(&mut cpu);
         cpu.acc = 20;
         cpu.evaluate(OpCode::new(0x48));
 
-        let stored_acc = cpu.fetch(((cpu.stack_pointer + 1) as u16));
+        let stored_acc = stack_entry(&mut cpu, 1);
         assert_eq!(cpu.acc, stored_acc)
     }
 
@@ -1336,7 +1841,7 @@ mod tests {
         let len = 0x10000;
         let mut memory = vec![0; len];
         let acc = 20;
-        memory[0x00FF] = acc;
+        memory[0x01FF] = acc;
         let mut cpu = create_test_cpu(memory);
         reset_cpu(&mut cpu);
         cpu.stack_pointer -= 1;
@@ -1364,7 +1869,6 @@ mod tests {
     fn test_set_flag() {
         let mut cpu = create_test_cpu(vec![0x38]);
         reset_cpu(&mut cpu);
-        cpu.status = Flags::PLACEHOLDER;
         cpu.evaluate(OpCode::new(0x38));
         info!("{:?}", cpu);
         assert_eq!(cpu.status, Flags::PLACEHOLDER | Flags::CARRY)
@@ -1444,7 +1948,7 @@ mod tests {
 
     #[test]
     fn test_indirect_indexed() {
-        let mut cpu = create_test_cpu(vec![0x05, 0x0, 0b1111_1111, 0x0]);
+        let mut cpu = create_test_cpu(vec![0x11, 0x02, 0x03, 0x00, 0x00, 0b1111_1111]);
         reset_cpu(&mut cpu);
         cpu.reg_y = 2;
         cpu.evaluate(OpCode::new(0x11));
@@ -1470,4 +1974,352 @@ mod tests {
         assert_eq!(cpu.acc, 0b1111_1111);
         assert_eq!(cpu.status, Flags::NEGATIVE | Flags::PLACEHOLDER)
     }
+
+    #[test]
+    fn test_lax() {
+        let mut cpu = create_test_cpu(vec![0xA7, 0x02, 0x85]);
+        reset_cpu(&mut cpu);
+        let cycles = cpu.evaluate(OpCode::new(0xA7));
+        assert_eq!(cpu.acc, 0x85);
+        assert_eq!(cpu.reg_x, 0x85);
+        assert_eq!(cycles, 3);
+        assert_eq!(cpu.status, Flags::NEGATIVE | Flags::PLACEHOLDER)
+    }
+
+    #[test]
+    fn test_lax_zero_page_y() {
+        let mut cpu = create_test_cpu(vec![0xB7, 0x01, 0x00, 0x42]);
+        reset_cpu(&mut cpu);
+        cpu.reg_x = 0x20;
+        cpu.reg_y = 0x02;
+        cpu.evaluate(OpCode::new(0xB7));
+        assert_eq!(cpu.acc, 0x42);
+        assert_eq!(cpu.reg_x, 0x42);
+        assert_eq!(cpu.program_counter, 2)
+    }
+
+    #[test]
+    fn test_sax() {
+        let mut cpu = create_test_cpu(vec![0x87, 0x03, 0x00, 0x00]);
+        reset_cpu(&mut cpu);
+        cpu.acc = 0b1100;
+        cpu.reg_x = 0b1010;
+        let cycles = cpu.evaluate(OpCode::new(0x87));
+        assert_eq!(cpu.fetch(3), 0b1000);
+        assert_eq!(cycles, 3)
+    }
+
+    #[test]
+    fn test_dcp() {
+        let mut cpu = create_test_cpu(vec![0xC7, 0x02, 0x06]);
+        reset_cpu(&mut cpu);
+        cpu.acc = 5;
+        let cycles = cpu.evaluate(OpCode::new(0xC7));
+        assert_eq!(cpu.fetch(2), 5);
+        assert_eq!(cycles, 5);
+        assert_eq!(cpu.status, Flags::PLACEHOLDER | Flags::ZERO | Flags::CARRY)
+    }
+
+    #[test]
+    fn test_isb() {
+        let mut cpu = create_test_cpu(vec![0xE7, 0x02, 0x01]);
+        reset_cpu(&mut cpu);
+        cpu.status = Flags::PLACEHOLDER | Flags::CARRY;
+        cpu.acc = 5;
+        cpu.evaluate(OpCode::new(0xE7));
+        assert_eq!(cpu.fetch(2), 2);
+        assert_eq!(cpu.acc, 3);
+        assert_eq!(cpu.status, Flags::PLACEHOLDER | Flags::CARRY)
+    }
+
+    #[test]
+    fn test_slo() {
+        let mut cpu = create_test_cpu(vec![0x07, 0x02, 0b1000_0001]);
+        reset_cpu(&mut cpu);
+        cpu.acc = 0b0000_0100;
+        cpu.evaluate(OpCode::new(0x07));
+        assert_eq!(cpu.fetch(2), 0b0000_0010);
+        assert_eq!(cpu.acc, 0b0000_0110);
+        assert_eq!(cpu.status, Flags::PLACEHOLDER | Flags::CARRY)
+    }
+
+    #[test]
+    fn test_rra() {
+        let mut cpu = create_test_cpu(vec![0x67, 0x02, 0b0000_0101]);
+        reset_cpu(&mut cpu);
+        cpu.acc = 1;
+        cpu.evaluate(OpCode::new(0x67));
+        assert_eq!(cpu.fetch(2), 0b0000_0010);
+        assert_eq!(cpu.acc, 4);
+        assert_eq!(cpu.status, Flags::PLACEHOLDER)
+    }
+
+    #[test]
+    fn test_anc() {
+        let mut cpu = create_test_cpu(vec![0x0B, 0b1000_0000]);
+        reset_cpu(&mut cpu);
+        cpu.acc = 0xFF;
+        cpu.evaluate(OpCode::new(0x0B));
+        assert_eq!(cpu.acc, 0x80);
+        assert_eq!(cpu.status, Flags::PLACEHOLDER | Flags::NEGATIVE | Flags::CARRY)
+    }
+
+    #[test]
+    fn test_arr() {
+        let mut cpu = create_test_cpu(vec![0x6B, 0xFF]);
+        reset_cpu(&mut cpu);
+        cpu.acc = 0xFF;
+        cpu.evaluate(OpCode::new(0x6B));
+        assert_eq!(cpu.acc, 0x7F);
+        assert_eq!(cpu.status, Flags::PLACEHOLDER | Flags::CARRY)
+    }
+
+    #[test]
+    fn test_axs() {
+        let mut cpu = create_test_cpu(vec![0xCB, 0x02]);
+        reset_cpu(&mut cpu);
+        cpu.acc = 0x0F;
+        cpu.reg_x = 0x07;
+        cpu.evaluate(OpCode::new(0xCB));
+        assert_eq!(cpu.reg_x, 0x05);
+        assert_eq!(cpu.status, Flags::PLACEHOLDER | Flags::CARRY)
+    }
+
+    #[test]
+    fn test_unofficial_nop() {
+        let mut cpu = create_test_cpu(vec![]);
+        reset_cpu(&mut cpu);
+        cpu.evaluate(OpCode::new(0x1A));
+        assert_eq!(cpu.program_counter, 1);
+
+        reset_cpu(&mut cpu);
+        cpu.store(0x1C, Some(0));
+        cpu.store(0xFF, Some(1));
+        cpu.reg_x = 1;
+        let cycles = cpu.evaluate(OpCode::new(0x1C));
+        assert_eq!(cpu.program_counter, 3);
+        assert_eq!(cycles, 5)
+    }
+
+    #[test]
+    fn test_jam() {
+        let mut cpu = create_test_cpu(vec![0x02]);
+        reset_cpu(&mut cpu);
+        cpu.evaluate(OpCode::new(0x02));
+        assert_eq!(cpu.program_counter, 0)
+    }
+
+    #[test]
+    fn test_bus_cycles_match_op_code_table() {
+        // Branches are covered separately
+        for value in (0x00..=0xFF).filter(|value| OpCode::new(*value).info().addressing.mode != Relative) {
+            let mut cpu = create_test_cpu(vec![]);
+            reset_cpu(&mut cpu);
+            cpu.status = Flags::PLACEHOLDER;
+            cpu.program_counter = 0x200;
+            let cycles = cpu.evaluate(OpCode::new(value));
+            assert_eq!(cycles, OpCode::new(value).info().cycles, "{:#04X}", value);
+        }
+    }
+
+    #[test]
+    fn test_page_cross_cycles() {
+        let mut cpu = create_test_cpu(vec![0xBD, 0xFF, 0x00]);
+        reset_cpu(&mut cpu);
+        cpu.reg_x = 1;
+        assert_eq!(cpu.evaluate(OpCode::new(0xBD)), 5);
+
+        let mut cpu = create_test_cpu(vec![0x9D, 0x00, 0x01, 0x00]);
+        reset_cpu(&mut cpu);
+        cpu.reg_x = 1;
+        assert_eq!(cpu.evaluate(OpCode::new(0x9D)), 5);
+    }
+
+    #[test]
+    fn test_branch_cycles() {
+        let mut cpu = create_test_cpu(vec![]);
+        reset_cpu(&mut cpu);
+        cpu.status = Flags::PLACEHOLDER | Flags::ZERO;
+        cpu.program_counter = 0x200;
+        assert_eq!(cpu.evaluate(OpCode::new(0xD0)), 2);
+
+        reset_cpu(&mut cpu);
+        cpu.status = Flags::PLACEHOLDER;
+        cpu.program_counter = 0x200;
+        cpu.store(0x10, Some(0x201));
+        assert_eq!(cpu.evaluate(OpCode::new(0xD0)), 3);
+        assert_eq!(cpu.program_counter, 0x212);
+
+        reset_cpu(&mut cpu);
+        cpu.status = Flags::PLACEHOLDER;
+        cpu.program_counter = 0x2F0;
+        cpu.store(0x20, Some(0x2F1));
+        assert_eq!(cpu.evaluate(OpCode::new(0xD0)), 4);
+        assert_eq!(cpu.program_counter, 0x312);
+    }
+
+    #[test]
+    fn test_irq_polling() {
+        let mut cpu = create_test_cpu(vec![0xEA, 0xEA]);
+        reset_cpu(&mut cpu);
+        cpu.bus.irq = true;
+        cpu.evaluate(OpCode::new(0xEA));
+        assert_eq!(cpu.pending_interrupt, Some(Interrupt::Irq));
+
+        cpu.status = Flags::PLACEHOLDER | Flags::IRQ_DIS;
+        cpu.evaluate(OpCode::new(0xEA));
+        assert_eq!(cpu.pending_interrupt, None);
+
+        cpu.bus.nmi = true;
+        cpu.evaluate(OpCode::new(0xEA));
+        assert_eq!(cpu.pending_interrupt, Some(Interrupt::Nmi))
+    }
+
+    #[test]
+    fn test_irq_line_shared_between_sources() {
+        let mut bus = Bus::new(vec![0; 0x800], Ppu::new(vec![], NameTableMirroring::VERTICAL), Cartridge::new());
+        bus.set_irq(IrqSource::FRAME_COUNTER, true);
+        bus.set_irq(IrqSource::DMC, true);
+        bus.set_irq(IrqSource::FRAME_COUNTER, false);
+        assert!(bus.irq());
+        bus.set_irq(IrqSource::DMC, false);
+        assert!(!bus.irq())
+    }
+
+    // Runs the program from $0000 of a real bus with $0200-$02FF counting up, returns the CPU after its
+    // last instruction and how many cycles that instruction took including the DMA
+    fn run_oam_dma(source: &str, instructions: usize) -> (Cpu<Bus>, u64) {
+        let mut bus = Bus::new(vec![0; 0x800], Ppu::new(vec![], NameTableMirroring::VERTICAL), Cartridge::new());
+        for (offset, byte) in assemble(source, 0x0000).unwrap().iter().enumerate() {
+            bus.store(*byte, offset as u16);
+        }
+        for offset in 0..=0xFF {
+            bus.store(offset as u8, 0x0200 + offset);
+        }
+        bus.store(0x0A, 0x0010);
+        let mut cpu = Cpu::new(bus, Some(0));
+        for _ in 1..instructions {
+            cpu.step();
+        }
+        let before = cpu.total_cycles;
+        cpu.step();
+        let cycles = cpu.total_cycles - before;
+        (cpu, cycles)
+    }
+
+    #[test]
+    fn test_oam_dma() {
+        // The DMA starts on an odd cycle after LDA # and on an even one after LDA zp
+        let (mut cpu, cycles) = run_oam_dma("LDA #$0A\nSTA $4014", 2);
+        assert_eq!(cycles, 4 + 514);
+        let (_, cycles) = run_oam_dma("LDA $10\nSTA $4014", 2);
+        assert_eq!(cycles, 4 + 513);
+        // Page $0A mirrors $0200 and OAMADDR wrapped back to where the copy started
+        cpu.bus_mut().store(0x42, 0x2003);
+        assert_eq!(cpu.bus().peek(0x2004), 0x42);
+        cpu.bus_mut().store(0xFF, 0x2003);
+        assert_eq!(cpu.bus().peek(0x2004), 0xFF)
+    }
+
+    #[test]
+    fn test_cli_delays_irq() {
+        let mut cpu = create_test_cpu_from_source("CLI\nNOP");
+        reset_cpu(&mut cpu);
+        cpu.status = Flags::PLACEHOLDER | Flags::IRQ_DIS;
+        cpu.bus.irq = true;
+        cpu.evaluate(OpCode::new(0x58));
+        assert_eq!(cpu.pending_interrupt, None);
+        cpu.evaluate(OpCode::new(0xEA));
+        assert_eq!(cpu.pending_interrupt, Some(Interrupt::Irq))
+    }
+
+    #[test]
+    fn test_sei_lets_irq_through() {
+        let mut cpu = create_test_cpu_from_source("SEI\nNOP");
+        reset_cpu(&mut cpu);
+        cpu.bus.irq = true;
+        cpu.evaluate(OpCode::new(0x78));
+        assert_eq!(cpu.pending_interrupt, Some(Interrupt::Irq));
+        cpu.evaluate(OpCode::new(0xEA));
+        assert_eq!(cpu.pending_interrupt, None)
+    }
+
+    #[test]
+    fn test_power_on() {
+        let cpu = create_test_cpu_with_vectors(0x1111, 0x8000, 0x2222);
+        assert_eq!(cpu.program_counter, 0x8000);
+        assert_eq!(cpu.stack_pointer, 0xFD);
+        assert_eq!(cpu.status.bits(), 0x24);
+        assert_eq!((cpu.acc, cpu.reg_x, cpu.reg_y), (0, 0, 0))
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut cpu = create_test_cpu_with_vectors(0x1111, 0x8000, 0x2222);
+        cpu.program_counter = 0x0200;
+        cpu.acc = 0x12;
+        cpu.status = Flags::PLACEHOLDER | Flags::CARRY;
+        cpu.bus.store(0xAB, 0x01FD);
+        let cycles = cpu.reset();
+        assert_eq!(cycles, 7);
+        assert_eq!(cpu.program_counter, 0x8000);
+        assert_eq!(cpu.stack_pointer, 0xFA);
+        assert_eq!(cpu.acc, 0x12);
+        assert_eq!(cpu.status, Flags::PLACEHOLDER | Flags::CARRY | Flags::IRQ_DIS);
+        assert_eq!(cpu.bus.fetch(0x01FD), 0xAB)
+    }
+
+    #[test]
+    fn test_nmi_sequence() {
+        let mut cpu = create_test_cpu_with_vectors(0x9000, 0x8000, 0x2222);
+        cpu.program_counter = 0x0234;
+        cpu.status = Flags::PLACEHOLDER | Flags::NEGATIVE;
+        let cycles = cpu.nmi_interrupt();
+        assert_eq!(cycles, 7);
+        assert_eq!(cpu.program_counter, 0x9000);
+        assert!(cpu.status.contains(Flags::IRQ_DIS));
+        assert_eq!(cpu.bus.fetch(0x01FD), 0x02);
+        assert_eq!(cpu.bus.fetch(0x01FC), 0x34);
+        assert_eq!(cpu.bus.fetch(0x01FB), (Flags::PLACEHOLDER | Flags::NEGATIVE).bits())
+    }
+
+    fn run_with_decimal(variant: CpuVariant, source: &str, acc: u8, carry: bool) -> Cpu<FlatBus> {
+        let mut cpu = create_test_cpu_from_source(source);
+        reset_cpu(&mut cpu);
+        cpu.set_variant(variant);
+        cpu.acc = acc;
+        cpu.status = Flags::PLACEHOLDER | Flags::DECIMAL;
+        cpu.status.set_flag(carry, Flags::CARRY);
+        let op_code = cpu.fetch(0);
+        cpu.evaluate(OpCode::new(op_code));
+        cpu
+    }
+
+    #[test]
+    fn test_decimal_add() {
+        let cpu = run_with_decimal(CpuVariant::Nmos6502, "ADC #$46", 0x58, true);
+        assert_eq!(cpu.acc, 0x05);
+        assert!(cpu.status.contains(Flags::CARRY));
+
+        let cpu = run_with_decimal(CpuVariant::Nmos6502, "ADC #$34", 0x12, false);
+        assert_eq!(cpu.acc, 0x46);
+        assert!(!cpu.status.contains(Flags::CARRY));
+
+        let cpu = run_with_decimal(CpuVariant::Ricoh2A03, "ADC #$01", 0x09, false);
+        assert_eq!(cpu.acc, 0x0A)
+    }
+
+    #[test]
+    fn test_decimal_subtract() {
+        let cpu = run_with_decimal(CpuVariant::Nmos6502, "SBC #$13", 0x40, true);
+        assert_eq!(cpu.acc, 0x27);
+        assert!(cpu.status.contains(Flags::CARRY));
+
+        let cpu = run_with_decimal(CpuVariant::Nmos6502, "SBC #$34", 0x21, true);
+        assert_eq!(cpu.acc, 0x87);
+        assert!(!cpu.status.contains(Flags::CARRY));
+
+        let cpu = run_with_decimal(CpuVariant::Ricoh2A03, "SBC #$01", 0x10, true);
+        assert_eq!(cpu.acc, 0x0F)
+    }
 }
@@ -0,0 +1,170 @@
+use std::fmt;
+use crate::bus::CpuBus;
+use crate::op_code::{OP_CODE_TABLE, OpCodeInfo};
+use crate::addressing::AddressingMode;
+use crate::util::combine_u8;
+
+static PRG_BANK_SIZE: usize = 0x4000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Instruction {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub info: &'static OpCodeInfo
+}
+
+impl Instruction {
+    // Operand in standard syntax, branches show their target
+    pub fn operand(&self) -> String {
+        let register = self.info.addressing.register
+            .map(|register| format!("{:?}", register))
+            .unwrap_or_default();
+        match self.info.addressing.mode {
+            AddressingMode::Implied => String::new(),
+            AddressingMode::Accumulator => String::from("A"),
+            AddressingMode::Immediate => format!("#${:02X}", self.bytes[1]),
+            AddressingMode::ZeroPage => format!("${:02X}", self.bytes[1]),
+            AddressingMode::ZeroPageIndexed => format!("${:02X},{}", self.bytes[1], register),
+            AddressingMode::Absolute => format!("${:04X}", self.argument()),
+            AddressingMode::AbsoluteIndexed => format!("${:04X},{}", self.argument(), register),
+            AddressingMode::Indirect => format!("(${:04X})", self.argument()),
+            AddressingMode::IndexedIndirect => format!("(${:02X},X)", self.bytes[1]),
+            AddressingMode::IndirectIndexed => format!("(${:02X}),Y", self.bytes[1]),
+            AddressingMode::Relative => format!("${:04X}", self.branch_target())
+        }
+    }
+
+    // Operand bytes as a little endian word
+    pub fn argument(&self) -> u16 {
+        match self.bytes.len() {
+            3 => combine_u8(self.bytes[1], self.bytes[2]),
+            2 => self.bytes[1] as u16,
+            _ => 0
+        }
+    }
+
+    pub fn branch_target(&self) -> u16 {
+        self.next_address().wrapping_add(self.bytes[1] as i8 as u16)
+    }
+
+    pub fn next_address(&self) -> u16 {
+        self.address.wrapping_add(self.bytes.len() as u16)
+    }
+
+    pub fn raw_bytes(&self) -> String {
+        let bytes: Vec<String> = self.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        bytes.join(" ")
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operand = self.operand();
+        if operand.is_empty() {
+            write!(f, "{}", self.info.mnemonic)
+        } else {
+            write!(f, "{} {}", self.info.mnemonic, operand)
+        }
+    }
+}
+
+fn decode(address: u16, mut read: impl FnMut(u16) -> u8) -> Instruction {
+    let info = &OP_CODE_TABLE[read(address) as usize];
+    let bytes = (0..info.bytes as u16)
+        .map(|offset| read(address.wrapping_add(offset)))
+        .collect();
+    Instruction { address, bytes, info }
+}
+
+// Memory is peeked, disassembling over PPU ports leaves them alone
+pub fn disassemble<B: CpuBus>(bus: &B, address: u16) -> Instruction {
+    decode(address, |address| bus.peek(address))
+}
+
+// Disassembles every instruction starting between the two addresses, inclusive
+pub fn disassemble_range<B: CpuBus>(bus: &B, start: u16, end: u16) -> Vec<Instruction> {
+    let mut instructions = vec![];
+    let mut address = start as u32;
+    while address <= end as u32 {
+        let instruction = disassemble(bus, address as u16);
+        address += instruction.bytes.len() as u32;
+        instructions.push(instruction);
+    }
+    instructions
+}
+
+// Disassembles raw bytes as if they were mapped at the base address, running past the end reads zero
+pub fn disassemble_bytes(bytes: &[u8], base: u16) -> Vec<Instruction> {
+    let read = |address: u16| *bytes.get(address.wrapping_sub(base) as usize).unwrap_or(&0);
+    let mut instructions = vec![];
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let instruction = decode(base.wrapping_add(offset as u16), read);
+        offset += instruction.bytes.len();
+        instructions.push(instruction);
+    }
+    instructions
+}
+
+// Listing of every 16KB PRG bank, the last one at $C000 where the vectors live and the rest at $8000
+pub fn disassemble_prg_rom(prg_rom: &[u8]) -> String {
+    let banks = prg_rom.chunks(PRG_BANK_SIZE).count();
+    let mut listing = String::new();
+    for (bank, bytes) in prg_rom.chunks(PRG_BANK_SIZE).enumerate() {
+        let base = if bank == banks - 1 { 0xC000 } else { 0x8000 };
+        listing.push_str(&format!("; bank {} at ${:04X}\n", bank, base));
+        for instruction in disassemble_bytes(bytes, base) {
+            listing.push_str(&listing_line(&instruction));
+        }
+    }
+    listing
+}
+
+pub fn listing_line(instruction: &Instruction) -> String {
+    format!("{:04X}  {:<8}  {}\n", instruction.address, instruction.raw_bytes(), instruction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flat_bus::FlatBus;
+
+    #[test]
+    fn test_operand_syntax() {
+        let program = [
+            0xA9, 0x44, 0xB5, 0x10, 0xBE, 0x00, 0x03, 0x6C, 0xFF, 0x02,
+            0xA1, 0x80, 0xB1, 0x80, 0x0A, 0xE8, 0xD0, 0xFC
+        ];
+        let listing: Vec<String> = disassemble_bytes(&program, 0xC000).iter()
+            .map(|instruction| instruction.to_string())
+            .collect();
+        assert_eq!(listing, vec![
+            "LDA #$44", "LDA $10,X", "LDX $0300,Y", "JMP ($02FF)",
+            "LDA ($80,X)", "LDA ($80),Y", "ASL A", "INX", "BNE $C00E"
+        ])
+    }
+
+    #[test]
+    fn test_prg_rom_listing() {
+        let mut prg_rom = vec![0xEA; PRG_BANK_SIZE];
+        prg_rom[0] = 0x4C;
+        prg_rom[1] = 0x00;
+        prg_rom[2] = 0xC0;
+        let listing = disassemble_prg_rom(&prg_rom);
+        let mut lines = listing.lines();
+        assert_eq!(lines.next(), Some("; bank 0 at $C000"));
+        assert_eq!(lines.next(), Some("C000  4C 00 C0  JMP $C000"));
+        assert_eq!(lines.next(), Some("C003  EA        NOP"))
+    }
+
+    #[test]
+    fn test_range_wraps_at_end_of_memory() {
+        let mut bus = FlatBus::with_program(&[0xEA, 0xAD, 0x34], 0xFFFD);
+        bus.store(0x12, 0x0000);
+        let listing: Vec<String> = disassemble_range(&bus, 0xFFFD, 0xFFFF).iter()
+            .map(listing_line)
+            .collect();
+        assert_eq!(listing, vec!["FFFD  EA        NOP\n", "FFFE  AD 34 12  LDA $1234\n"]);
+        assert_eq!(disassemble_range(&bus, 0xFFFF, 0xFFFF).len(), 1)
+    }
+}
@@ -1,5 +1,4 @@
 use std::fmt;
-use bitflags::_core::fmt::{Formatter, Error};
 
 bitflags! {
     pub struct Flags: u8 {
@@ -0,0 +1,100 @@
+use crate::bus::CpuBus;
+
+static ADDRESS_SPACE_SIZE: usize = 0x10000;
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum BusAccess {
+    Read, Write
+}
+
+// 64KB of plain RAM with nothing else mapped, lets the CPU run without a PPU or cartridge
+#[derive(Debug)]
+pub struct FlatBus {
+    memory: Vec<u8>,
+    cycle_log: Option<Vec<(u16, u8, BusAccess)>>,
+    pub nmi: bool,
+    pub irq: bool
+}
+
+impl FlatBus {
+    pub fn new(memory: Vec<u8>) -> FlatBus {
+        assert_eq!(memory.len(), ADDRESS_SPACE_SIZE, "Flat bus needs the whole address space");
+        FlatBus {
+            memory,
+            cycle_log: None,
+            nmi: false,
+            irq: false
+        }
+    }
+
+    // Program copied to the origin, the rest of memory is zeroed
+    pub fn with_program(program: &[u8], origin: u16) -> FlatBus {
+        let mut memory = vec![0; ADDRESS_SPACE_SIZE];
+        let origin = origin as usize;
+        memory[origin..(origin + program.len())].copy_from_slice(program);
+        FlatBus::new(memory)
+    }
+
+    // Every following fetch and store is recorded until the log is taken
+    pub fn record_cycles(&mut self) {
+        self.cycle_log = Some(vec![]);
+    }
+
+    pub fn take_cycle_log(&mut self) -> Vec<(u16, u8, BusAccess)> {
+        self.cycle_log.take().unwrap_or_default()
+    }
+
+    fn log_cycle(&mut self, address: u16, value: u8, access: BusAccess) {
+        if let Some(cycle_log) = self.cycle_log.as_mut() {
+            cycle_log.push((address, value, access));
+        }
+    }
+}
+
+impl CpuBus for FlatBus {
+    fn fetch(&mut self, address: u16) -> u8 {
+        let value = self.memory[address as usize];
+        self.log_cycle(address, value, BusAccess::Read);
+        value
+    }
+
+    fn store(&mut self, value: u8, address: u16) {
+        self.log_cycle(address, value, BusAccess::Write);
+        self.memory[address as usize] = value;
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.memory[address as usize]
+    }
+
+    fn tick(&mut self) {}
+
+    fn nmi(&self) -> bool {
+        self.nmi
+    }
+
+    fn acknowledge_nmi(&mut self) {
+        self.nmi = false;
+    }
+
+    fn irq(&self) -> bool {
+        self.irq
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cycle_log() {
+        let mut bus = FlatBus::with_program(&[0xA9, 0x01], 0x0200);
+        bus.fetch(0x0200);
+        bus.record_cycles();
+        bus.fetch(0x0201);
+        bus.store(0x02, 0xFFFF);
+        assert_eq!(bus.peek(0xFFFF), 0x02);
+        assert_eq!(bus.take_cycle_log(), vec![(0x0201, 0x01, BusAccess::Read), (0xFFFF, 0x02, BusAccess::Write)]);
+        assert_eq!(bus.take_cycle_log(), vec![])
+    }
+}
@@ -0,0 +1,306 @@
+extern crate winit;
+
+use std::collections::HashMap;
+use log::{info, warn};
+use sdl2::controller::{Button, GameController};
+use sdl2::GameControllerSubsystem;
+use winit_input_helper::WinitInputHelper;
+use self::winit::event::{Event, VirtualKeyCode};
+use crate::controller::ButtonState;
+
+static PORTS: usize = 2;
+static SLOTS: u8 = 10;
+
+// Keys that can be named in a key map file
+static BINDABLE_KEYS: &[VirtualKeyCode] = &[
+    VirtualKeyCode::A, VirtualKeyCode::B, VirtualKeyCode::C, VirtualKeyCode::D, VirtualKeyCode::E,
+    VirtualKeyCode::F, VirtualKeyCode::G, VirtualKeyCode::H, VirtualKeyCode::I, VirtualKeyCode::J,
+    VirtualKeyCode::K, VirtualKeyCode::L, VirtualKeyCode::M, VirtualKeyCode::N, VirtualKeyCode::O,
+    VirtualKeyCode::P, VirtualKeyCode::Q, VirtualKeyCode::R, VirtualKeyCode::S, VirtualKeyCode::T,
+    VirtualKeyCode::U, VirtualKeyCode::V, VirtualKeyCode::W, VirtualKeyCode::X, VirtualKeyCode::Y,
+    VirtualKeyCode::Z,
+    VirtualKeyCode::Key0, VirtualKeyCode::Key1, VirtualKeyCode::Key2, VirtualKeyCode::Key3, VirtualKeyCode::Key4,
+    VirtualKeyCode::Key5, VirtualKeyCode::Key6, VirtualKeyCode::Key7, VirtualKeyCode::Key8, VirtualKeyCode::Key9,
+    VirtualKeyCode::F1, VirtualKeyCode::F2, VirtualKeyCode::F3, VirtualKeyCode::F4, VirtualKeyCode::F5,
+    VirtualKeyCode::F6, VirtualKeyCode::F7, VirtualKeyCode::F8, VirtualKeyCode::F9, VirtualKeyCode::F10,
+    VirtualKeyCode::F11, VirtualKeyCode::F12,
+    VirtualKeyCode::Up, VirtualKeyCode::Down, VirtualKeyCode::Left, VirtualKeyCode::Right,
+    VirtualKeyCode::Escape, VirtualKeyCode::Space, VirtualKeyCode::Return, VirtualKeyCode::Back,
+    VirtualKeyCode::Tab, VirtualKeyCode::LShift, VirtualKeyCode::RShift, VirtualKeyCode::LControl,
+    VirtualKeyCode::RControl, VirtualKeyCode::LAlt, VirtualKeyCode::RAlt, VirtualKeyCode::Pause
+];
+
+static BUTTON_NAMES: &[(&str, ButtonState)] = &[
+    ("A", ButtonState::A), ("B", ButtonState::B), ("SELECT", ButtonState::SELECT), ("START", ButtonState::START),
+    ("UP", ButtonState::UP), ("DOWN", ButtonState::DOWN), ("LEFT", ButtonState::LEFT), ("RIGHT", ButtonState::RIGHT)
+];
+
+// NES A and B sit to the right of each other, like B and A on a modern pad
+static GAMEPAD_BUTTONS: &[(Button, ButtonState)] = &[
+    (Button::B, ButtonState::A), (Button::A, ButtonState::B), (Button::Back, ButtonState::SELECT),
+    (Button::Start, ButtonState::START), (Button::DPadUp, ButtonState::UP), (Button::DPadDown, ButtonState::DOWN),
+    (Button::DPadLeft, ButtonState::LEFT), (Button::DPadRight, ButtonState::RIGHT)
+];
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Hotkey {
+    Pause,
+    Reset,
+    SaveState,
+    LoadState,
+    SelectSlot(u8),
+    Trace,
+    // Acts for as long as the key is held
+    Rewind,
+    Quit
+}
+
+impl Hotkey {
+    fn from_name(name: &str) -> Option<Hotkey> {
+        match name {
+            "PAUSE" => Some(Hotkey::Pause),
+            "RESET" => Some(Hotkey::Reset),
+            "SAVE_STATE" => Some(Hotkey::SaveState),
+            "LOAD_STATE" => Some(Hotkey::LoadState),
+            "TRACE" => Some(Hotkey::Trace),
+            "REWIND" => Some(Hotkey::Rewind),
+            "QUIT" => Some(Hotkey::Quit),
+            _ => name.strip_prefix("SLOT_")
+                .and_then(|slot| slot.parse::<u8>().ok())
+                .filter(|slot| *slot < SLOTS)
+                .map(Hotkey::SelectSlot)
+        }
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+enum Binding {
+    Button(usize, ButtonState),
+    Hotkey(Hotkey)
+}
+
+// Which key drives which controller button or hotkey, a key has at most one binding
+#[derive(Clone, Debug)]
+pub struct KeyMap {
+    bindings: HashMap<VirtualKeyCode, Binding>
+}
+
+impl Default for KeyMap {
+    fn default() -> KeyMap {
+        let mut key_map = KeyMap { bindings: HashMap::new() };
+        key_map.bind_button(VirtualKeyCode::X, 0, ButtonState::A);
+        key_map.bind_button(VirtualKeyCode::Z, 0, ButtonState::B);
+        key_map.bind_button(VirtualKeyCode::RShift, 0, ButtonState::SELECT);
+        key_map.bind_button(VirtualKeyCode::Return, 0, ButtonState::START);
+        key_map.bind_button(VirtualKeyCode::Up, 0, ButtonState::UP);
+        key_map.bind_button(VirtualKeyCode::Down, 0, ButtonState::DOWN);
+        key_map.bind_button(VirtualKeyCode::Left, 0, ButtonState::LEFT);
+        key_map.bind_button(VirtualKeyCode::Right, 0, ButtonState::RIGHT);
+        key_map.bind_hotkey(VirtualKeyCode::P, Hotkey::Pause);
+        key_map.bind_hotkey(VirtualKeyCode::R, Hotkey::Reset);
+        key_map.bind_hotkey(VirtualKeyCode::F5, Hotkey::SaveState);
+        key_map.bind_hotkey(VirtualKeyCode::F7, Hotkey::LoadState);
+        key_map.bind_hotkey(VirtualKeyCode::F9, Hotkey::Trace);
+        key_map.bind_hotkey(VirtualKeyCode::Back, Hotkey::Rewind);
+        key_map.bind_hotkey(VirtualKeyCode::Escape, Hotkey::Quit);
+        let slot_keys = [
+            VirtualKeyCode::Key0, VirtualKeyCode::Key1, VirtualKeyCode::Key2, VirtualKeyCode::Key3, VirtualKeyCode::Key4,
+            VirtualKeyCode::Key5, VirtualKeyCode::Key6, VirtualKeyCode::Key7, VirtualKeyCode::Key8, VirtualKeyCode::Key9
+        ];
+        for (slot, key) in slot_keys.iter().enumerate() {
+            key_map.bind_hotkey(*key, Hotkey::SelectSlot(slot as u8));
+        }
+        key_map
+    }
+}
+
+impl KeyMap {
+    pub fn bind_button(&mut self, key: VirtualKeyCode, port: usize, button: ButtonState) {
+        assert!(port < PORTS, "Controller port {} does not exist", port);
+        self.bindings.insert(key, Binding::Button(port, button));
+    }
+
+    pub fn bind_hotkey(&mut self, key: VirtualKeyCode, hotkey: Hotkey) {
+        self.bindings.insert(key, Binding::Hotkey(hotkey));
+    }
+
+    // One binding per line, `KEY = PORT.BUTTON` or `KEY = HOTKEY`, e.g. `X = 1.A`, `F5 = SAVE_STATE` or `Key3 = SLOT_3`
+    pub fn parse(config: &str) -> Result<KeyMap, String> {
+        let mut key_map = KeyMap { bindings: HashMap::new() };
+        for (index, line) in config.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue
+            }
+            let error = |message: &str| format!("line {}: {}", index + 1, message);
+            let mut parts = line.splitn(2, '=').map(|part| part.trim());
+            let key_name = parts.next().unwrap();
+            let target = parts.next().ok_or_else(|| error("expected KEY = TARGET"))?;
+            let key = *BINDABLE_KEYS.iter()
+                .find(|key| format!("{:?}", key) == key_name)
+                .ok_or_else(|| error(&format!("unknown key {}", key_name)))?;
+            if let Some(hotkey) = Hotkey::from_name(target) {
+                key_map.bind_hotkey(key, hotkey);
+                continue
+            }
+            let mut target_parts = target.splitn(2, '.');
+            let port = target_parts.next().unwrap().parse::<usize>().ok()
+                .filter(|port| (1..=PORTS).contains(port))
+                .ok_or_else(|| error(&format!("unknown target {}", target)))?;
+            let button_name = target_parts.next().unwrap_or_default();
+            let button = BUTTON_NAMES.iter()
+                .find(|(name, _)| *name == button_name)
+                .map(|(_, button)| *button)
+                .ok_or_else(|| error(&format!("unknown button {}", button_name)))?;
+            key_map.bind_button(key, port - 1, button);
+        }
+        Ok(key_map)
+    }
+
+    pub fn buttons(&self, port: usize, held: impl Fn(VirtualKeyCode) -> bool) -> ButtonState {
+        self.bindings.iter()
+            .filter(|(key, _)| held(**key))
+            .fold(ButtonState::empty(), |buttons, (_, binding)| match binding {
+                Binding::Button(bound_port, button) if *bound_port == port => buttons | *button,
+                _ => buttons
+            })
+    }
+
+    pub fn hotkeys(&self, pressed: impl Fn(VirtualKeyCode) -> bool) -> Vec<Hotkey> {
+        self.bindings.iter()
+            .filter(|(key, _)| pressed(**key))
+            .filter_map(|(_, binding)| match binding {
+                Binding::Hotkey(hotkey) => Some(*hotkey),
+                _ => None
+            })
+            .collect()
+    }
+
+    pub fn is_held(&self, hotkey: Hotkey, held: impl Fn(VirtualKeyCode) -> bool) -> bool {
+        self.bindings.iter()
+            .any(|(key, binding)| *binding == Binding::Hotkey(hotkey) && held(*key))
+    }
+}
+
+// Game controllers opened through SDL2, the first one drives port 1 and the second port 2
+pub struct Gamepads {
+    subsystem: GameControllerSubsystem,
+    controllers: Vec<GameController>
+}
+
+impl Gamepads {
+    pub fn open() -> Option<Gamepads> {
+        let subsystem = sdl2::init().and_then(|sdl| sdl.game_controller())
+            .map_err(|error| warn!("Gamepads are not available: {}", error))
+            .ok()?;
+        let joysticks = subsystem.num_joysticks().unwrap_or(0);
+        let controllers: Vec<GameController> = (0..joysticks)
+            .filter(|index| subsystem.is_game_controller(*index))
+            .filter_map(|index| subsystem.open(index).ok())
+            .take(PORTS)
+            .collect();
+        for controller in controllers.iter() {
+            info!("Opened gamepad {}", controller.name());
+        }
+        Some(Gamepads { subsystem, controllers })
+    }
+
+    pub fn update(&self) {
+        self.subsystem.update();
+    }
+
+    pub fn buttons(&self, port: usize) -> ButtonState {
+        match self.controllers.get(port) {
+            Some(controller) => GAMEPAD_BUTTONS.iter()
+                .filter(|(button, _)| controller.button(*button))
+                .fold(ButtonState::empty(), |buttons, (_, state)| buttons | *state),
+            None => ButtonState::empty()
+        }
+    }
+}
+
+// Keyboard and gamepad state gathered from the window events
+pub struct Input {
+    helper: WinitInputHelper,
+    key_map: KeyMap,
+    gamepads: Option<Gamepads>
+}
+
+impl Input {
+    pub fn new(key_map: KeyMap) -> Input {
+        Input {
+            helper: WinitInputHelper::new(),
+            key_map,
+            gamepads: Gamepads::open()
+        }
+    }
+
+    // True once all events of a frame of the event loop were seen, the state is only valid then
+    pub fn update<T>(&mut self, event: &Event<T>) -> bool {
+        let updated = self.helper.update(event);
+        if updated {
+            if let Some(gamepads) = self.gamepads.as_ref() {
+                gamepads.update();
+            }
+        }
+        updated
+    }
+
+    pub fn buttons(&self, port: usize) -> ButtonState {
+        let keyboard = self.key_map.buttons(port, |key| self.helper.key_held(key));
+        let gamepad = self.gamepads.as_ref().map_or(ButtonState::empty(), |gamepads| gamepads.buttons(port));
+        keyboard | gamepad
+    }
+
+    // Hotkeys pressed since the last update, closing the window counts as quit
+    pub fn hotkeys(&self) -> Vec<Hotkey> {
+        let mut hotkeys = self.key_map.hotkeys(|key| self.helper.key_pressed(key));
+        if self.helper.quit() {
+            hotkeys.push(Hotkey::Quit);
+        }
+        hotkeys
+    }
+
+    pub fn rewinding(&self) -> bool {
+        self.key_map.is_held(Hotkey::Rewind, |key| self.helper.key_held(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_key_map() {
+        let key_map = KeyMap::default();
+        let held = [VirtualKeyCode::X, VirtualKeyCode::Up, VirtualKeyCode::P];
+        assert_eq!(key_map.buttons(0, |key| held.contains(&key)), ButtonState::A | ButtonState::UP);
+        assert_eq!(key_map.buttons(1, |key| held.contains(&key)), ButtonState::empty());
+        assert_eq!(key_map.hotkeys(|key| held.contains(&key)), vec![Hotkey::Pause])
+    }
+
+    #[test]
+    fn test_parse_key_map() {
+        let key_map = KeyMap::parse("
+            # second player on the left of the keyboard
+            W = 2.UP
+            Space = 1.START
+            F1 = RESET
+            F2 = TRACE
+            Key3 = SLOT_3").unwrap();
+        assert_eq!(key_map.buttons(1, |key| key == VirtualKeyCode::W), ButtonState::UP);
+        assert_eq!(key_map.buttons(0, |key| key == VirtualKeyCode::Space), ButtonState::START);
+        assert_eq!(key_map.hotkeys(|key| key == VirtualKeyCode::F1), vec![Hotkey::Reset]);
+        assert_eq!(key_map.hotkeys(|key| key == VirtualKeyCode::F2), vec![Hotkey::Trace]);
+        assert_eq!(key_map.hotkeys(|key| key == VirtualKeyCode::Key3), vec![Hotkey::SelectSlot(3)]);
+        assert_eq!(key_map.buttons(0, |key| key == VirtualKeyCode::X), ButtonState::empty())
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(KeyMap::parse("X = 3.A").unwrap_err(), "line 1: unknown target 3.A");
+        assert_eq!(KeyMap::parse("\nNope = 1.A").unwrap_err(), "line 2: unknown key Nope");
+        assert_eq!(KeyMap::parse("X = 1.TURBO").unwrap_err(), "line 1: unknown button TURBO");
+        assert_eq!(KeyMap::parse("X").unwrap_err(), "line 1: expected KEY = TARGET")
+    }
+}
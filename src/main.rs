@@ -1,38 +1,159 @@
+// Mnemonics, registers and flags keep their datasheet spelling
+#![allow(clippy::upper_case_acronyms)]
+
 #[macro_use]
-#[allow(warnings)]
 extern crate bitflags;
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use log::LevelFilter;
 use log4rs::append::console::ConsoleAppender;
-use log4rs::append::file::FileAppender;
-use log4rs::encode::pattern::PatternEncoder;
-use log4rs::config::{Appender, Config, Logger, Root};
+use log4rs::config::{Appender, Config, Root};
 use log4rs::Handle;
-use crate::bus::Bus;
-use crate::cpu::Cpu;
 use crate::cartridge::CartridgeLoader;
-use crate::ppu::Ppu;
 use crate::util::read_file;
 use crate::console::Console;
-use std::fs::File;
+use crate::trace::Tracer;
+use crate::input::KeyMap;
+use crate::movie::{play_movie, Movie, MovieSession};
+use crate::rewind::Rewind;
+use crate::test_rom::{run_test_rom, run_test_rom_directory};
+use crate::disassembler::{disassemble_prg_rom, disassemble_range, listing_line};
+use std::fs::{self, File};
+use std::env;
+use std::io::BufWriter;
 
 mod cpu;
 mod op_code;
 mod bus;
+#[cfg(test)]
+mod flat_bus;
 mod addressing;
 mod util;
 mod flags;
 mod cartridge;
+mod controller;
+mod input;
+mod movie;
+mod rewind;
+mod save_state;
 mod ppu;
 mod screen;
 mod console;
+mod trace;
+mod test_rom;
+mod disassembler;
+#[cfg(test)]
+mod assembler;
+#[cfg(test)]
+mod processor_tests;
 
 fn main() {
     configure_logging();
-    let cartridge_path = Path::new("rom/nestest.nes");
-    let logfile = File::create("testing/output.txt").unwrap();
-    Console::power(&cartridge_path, &logfile);
+    // Usage: r_nes [rom.nes] [--trace trace.log] [--keys keys.cfg] [--record movie.fm2 | --play movie.fm2] [--rewind seconds] [--rewind-interval frames] | r_nes test <rom.nes or directory> | r_nes disasm <rom.nes> [start end] | r_nes play <rom.nes> <movie.fm2> <state>
+    match env::args().nth(1).as_deref() {
+        Some("test") => {
+            run_test_roms(Path::new(&env::args().nth(2).expect("test needs a rom or a directory")));
+            return
+        },
+        Some("disasm") => {
+            let cartridge_path = env::args().nth(2).expect("disasm needs a rom");
+            let cartridge = CartridgeLoader::load_cartridge(read_file(Path::new(&cartridge_path)));
+            match (env::args().nth(3), env::args().nth(4)) {
+                // A range is read through the booted console's bus, so it shows the banks mapped at power on
+                (Some(start), Some(end)) => {
+                    let cpu = Console::boot(cartridge);
+                    for instruction in disassemble_range(cpu.bus(), parse_address(&start), parse_address(&end)) {
+                        print!("{}", listing_line(&instruction));
+                    }
+                },
+                _ => print!("{}", disassemble_prg_rom(cartridge.prg_rom()))
+            }
+            return
+        },
+        Some("play") => {
+            let mut args = env::args().skip(2);
+            let cartridge_path = args.next().expect("play needs a rom");
+            let movie_path = args.next().expect("play needs a movie");
+            let state_path = args.next().expect("play needs an output file for the final state");
+            let movie = Movie::parse(&fs::read_to_string(&movie_path).unwrap())
+                .unwrap_or_else(|error| panic!("Invalid movie {}, {}", movie_path, error));
+            let mut cpu = Console::boot(CartridgeLoader::load_cartridge(read_file(Path::new(&cartridge_path))));
+            play_movie(&mut cpu, &movie);
+            fs::write(&state_path, Console::save_state(&cpu)).unwrap();
+            return
+        },
+        _ => {}
+    }
+    let mut cartridge_path = String::from("rom/nestest.nes");
+    let mut tracer = None;
+    let mut key_map = KeyMap::default();
+    let mut movie = None;
+    let mut record = None;
+    let mut rewind_seconds = 10;
+    let mut rewind_interval = 1;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--trace" => {
+                let path = args.next().expect("--trace needs an output file");
+                let logfile = File::create(path).unwrap();
+                tracer = Some(Tracer::new(Box::new(BufWriter::new(logfile))));
+            },
+            "--keys" => {
+                let path = args.next().expect("--keys needs a key map file");
+                let config = fs::read_to_string(&path).unwrap();
+                key_map = KeyMap::parse(&config).unwrap_or_else(|error| panic!("Invalid key map {}, {}", path, error));
+            },
+            "--record" => {
+                record = Some(PathBuf::from(args.next().expect("--record needs an output file")));
+                movie = None;
+            },
+            "--play" => {
+                let path = args.next().expect("--play needs a movie file");
+                let recorded = Movie::parse(&fs::read_to_string(&path).unwrap())
+                    .unwrap_or_else(|error| panic!("Invalid movie {}, {}", path, error));
+                movie = Some(MovieSession::Playing { movie: recorded, frame: 0 });
+                record = None;
+            },
+            "--rewind" => {
+                let seconds = args.next().expect("--rewind needs the seconds of history");
+                rewind_seconds = seconds.parse().unwrap_or_else(|_| panic!("Invalid rewind seconds {}", seconds));
+            },
+            "--rewind-interval" => {
+                let frames = args.next().expect("--rewind-interval needs a number of frames");
+                rewind_interval = frames.parse().ok().filter(|frames| *frames > 0)
+                    .unwrap_or_else(|| panic!("Invalid rewind interval {}", frames));
+            },
+            _ => cartridge_path = arg
+        }
+    }
+    // The rom can follow --record, so the movie is only named once every argument is read
+    if let Some(path) = record {
+        let rom_filename = Path::new(&cartridge_path).file_stem().unwrap_or_default().to_string_lossy().to_string();
+        movie = Some(MovieSession::Recording { movie: Movie::new(&rom_filename), path });
+    }
+    let rewind = Rewind::new(rewind_seconds, rewind_interval);
+    Console::power(Path::new(&cartridge_path), tracer, key_map, movie, rewind);
+}
+
+fn parse_address(address: &str) -> u16 {
+    u16::from_str_radix(address.trim_start_matches('$'), 16).unwrap_or_else(|_| panic!("Invalid address {}", address))
+}
+
+fn run_test_roms(path: &Path) {
+    let results = if path.is_dir() {
+        run_test_rom_directory(path)
+    } else {
+        vec![(path.to_path_buf(), run_test_rom(path))]
+    };
+    for (rom, result) in results.iter() {
+        println!("{:?} {}: {}", result.status, rom.display(), result.message);
+    }
+    let failed = results.iter().filter(|(_, result)| !result.passed()).count();
+    println!("{} passed, {} failed", results.len() - failed, failed);
+    if failed > 0 {
+        std::process::exit(1)
+    }
 }
 
 fn configure_logging() -> Handle {
@@ -0,0 +1,328 @@
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use log::{info, warn};
+use crate::controller::ButtonState;
+use crate::cpu::Cpu;
+use crate::util::nth_bit;
+
+// Button columns of an FM2 input line, from bit 7 down to bit 0 of ButtonState
+static BUTTON_COLUMNS: &str = "RLDUTSBA";
+
+static PORTS: usize = 2;
+
+bitflags! {
+    // First column of an FM2 input line
+    pub struct MovieCommand: u8 {
+        const SOFT_RESET = 0b0000_0001;
+        const HARD_RESET = 0b0000_0010;
+        const FDS_INSERT = 0b0000_0100;
+        const FDS_SELECT = 0b0000_1000;
+        const VS_INSERT_COIN = 0b0001_0000;
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct MovieFrame {
+    pub commands: MovieCommand,
+    pub ports: [ButtonState; 2]
+}
+
+impl Default for MovieFrame {
+    fn default() -> MovieFrame {
+        MovieFrame {
+            commands: MovieCommand::empty(),
+            ports: [ButtonState::empty(); 2]
+        }
+    }
+}
+
+// Input log in FCEUX's FM2 text format, one frame per line starting from power on
+#[derive(Clone, Debug, PartialEq)]
+pub struct Movie {
+    header: Vec<(String, String)>,
+    pub frames: Vec<MovieFrame>
+}
+
+impl Movie {
+    // The ROM checksum is not computed, FCEUX warns about it but still plays the movie
+    pub fn new(rom_filename: &str) -> Movie {
+        let header = [
+            ("version", "3"),
+            ("emuVersion", "22020"),
+            ("rerecordCount", "0"),
+            ("palFlag", "0"),
+            ("romFilename", rom_filename),
+            ("guid", "00000000-0000-0000-0000-000000000000"),
+            ("fourscore", "0"),
+            ("port0", "1"),
+            ("port1", "1"),
+            ("port2", "0")
+        ];
+        Movie {
+            header: header.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect(),
+            frames: vec![]
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Movie, String> {
+        let mut movie = Movie { header: vec![], frames: vec![] };
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: String| format!("line {}: {}", index + 1, message);
+            if line.starts_with('|') {
+                movie.frames.push(Movie::parse_frame(line).map_err(error)?);
+            } else if !line.trim().is_empty() {
+                let mut parts = line.splitn(2, ' ');
+                let key = parts.next().unwrap().to_string();
+                let value = parts.next().unwrap_or_default().to_string();
+                movie.header.push((key, value));
+            }
+        }
+        if movie.header("fourscore") == Some("1") {
+            return Err(String::from("four score movies are not supported"))
+        }
+        Ok(movie)
+    }
+
+    // |commands|port 0|port 1|port 2|
+    fn parse_frame(line: &str) -> Result<MovieFrame, String> {
+        let fields: Vec<&str> = line.split('|').collect();
+        if fields.len() < 4 {
+            return Err(format!("expected |commands|port0|port1|..., got {}", line))
+        }
+        let commands = fields[1].trim().parse::<u8>()
+            .map_err(|_| format!("invalid commands {}", fields[1]))?;
+        let mut frame = MovieFrame {
+            commands: MovieCommand::from_bits_truncate(commands),
+            ports: [ButtonState::empty(); 2]
+        };
+        for port in 0..PORTS {
+            frame.ports[port] = Movie::parse_buttons(fields[port + 2])?;
+        }
+        Ok(frame)
+    }
+
+    // Space and dot are released, any other character is pressed
+    fn parse_buttons(field: &str) -> Result<ButtonState, String> {
+        if field.is_empty() {
+            return Ok(ButtonState::empty())
+        }
+        if field.chars().count() != BUTTON_COLUMNS.len() {
+            return Err(format!("expected {} button columns, got {}", BUTTON_COLUMNS.len(), field))
+        }
+        let bits = field.chars().fold(0, |bits, column| (bits << 1) | (column != ' ' && column != '.') as u8);
+        Ok(ButtonState::from_bits_truncate(bits))
+    }
+
+    fn format_buttons(buttons: ButtonState) -> String {
+        BUTTON_COLUMNS.chars().enumerate()
+            .map(|(index, column)| if nth_bit(buttons.bits(), 7 - index as u8) { column } else { '.' })
+            .collect()
+    }
+
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.header.iter()
+            .find(|(header_key, _)| header_key == key)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (key, value) in self.header.iter() {
+            writeln!(f, "{} {}", key, value)?;
+        }
+        for frame in self.frames.iter() {
+            writeln!(f, "|{}|{}|{}||", frame.commands.bits(),
+                     Movie::format_buttons(frame.ports[0]), Movie::format_buttons(frame.ports[1]))?;
+        }
+        Ok(())
+    }
+}
+
+// A movie being recorded from or played into the frontend
+pub enum MovieSession {
+    Recording { movie: Movie, path: PathBuf },
+    Playing { movie: Movie, frame: usize }
+}
+
+impl MovieSession {
+    // Called on every frame boundary with the player's input, returns the input the frame runs with
+    pub fn next_frame(&mut self, live: MovieFrame) -> MovieFrame {
+        match self {
+            MovieSession::Recording { movie, .. } => {
+                movie.frames.push(live);
+                live
+            },
+            MovieSession::Playing { movie, frame } => {
+                *frame += 1;
+                match movie.frames.get(*frame - 1) {
+                    Some(recorded) => *recorded,
+                    None => {
+                        if *frame == movie.frames.len() + 1 {
+                            info!("Movie finished after {} frames", movie.frames.len());
+                        }
+                        live
+                    }
+                }
+            }
+        }
+    }
+
+    // Moves back to the given number of played frames, recordings drop the input after it
+    pub fn rewind_to(&mut self, frames: usize) {
+        match self {
+            MovieSession::Recording { movie, .. } => movie.frames.truncate(frames),
+            MovieSession::Playing { frame, .. } => *frame = frames.min(*frame)
+        }
+    }
+
+    pub fn finish(&self) {
+        if let MovieSession::Recording { movie, path } = self {
+            info!("Writing {} movie frames to {}", movie.frames.len(), path.display());
+            if let Err(error) = fs::write(path, movie.to_string()) {
+                warn!("Could not write movie {}: {}", path.display(), error);
+            }
+        }
+    }
+}
+
+// Power and reset commands first, then the buttons the frame is played with
+pub fn apply_frame(cpu: &mut Cpu, frame: &MovieFrame) {
+    if frame.commands.contains(MovieCommand::HARD_RESET) {
+        cpu.power_on();
+    } else if frame.commands.contains(MovieCommand::SOFT_RESET) {
+        cpu.reset();
+    }
+    for port in 0..PORTS {
+        cpu.bus_mut().set_buttons(port, frame.ports[port]);
+    }
+}
+
+// Emulates until the PPU enters the next vblank
+pub fn run_frame(cpu: &mut Cpu) {
+    let frame = cpu.bus().frame();
+    while cpu.bus().frame() == frame {
+        cpu.emulate();
+    }
+}
+
+// Plays a movie from power on without a window, the console is left after the last frame
+pub fn play_movie(cpu: &mut Cpu, movie: &Movie) {
+    for frame in movie.frames.iter() {
+        apply_frame(cpu, frame);
+        run_frame(cpu);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::bus::CpuBus;
+    use crate::cartridge::CartridgeLoader;
+    use crate::console::Console;
+
+    static FM2: &str = "version 3
+emuVersion 22020
+romFilename test
+fourscore 0
+port0 1
+port1 1
+port2 0
+|0|........|........||
+|0|R......A|...U....||
+|1|.L....B.|........||
+";
+
+    #[test]
+    fn test_parse_and_write() {
+        let movie = Movie::parse(FM2).unwrap();
+        assert_eq!(movie.header("romFilename"), Some("test"));
+        assert_eq!(movie.frames.len(), 3);
+        assert_eq!(movie.frames[1].ports, [ButtonState::RIGHT | ButtonState::A, ButtonState::UP]);
+        assert_eq!(movie.frames[2].commands, MovieCommand::SOFT_RESET);
+        assert_eq!(movie.frames[2].ports[0], ButtonState::LEFT | ButtonState::B);
+        assert_eq!(movie.to_string(), FM2)
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Movie::parse("version 3\n|x|........|||").unwrap_err(), "line 2: invalid commands x");
+        assert_eq!(Movie::parse("|0|...|........||").unwrap_err(), "line 1: expected 8 button columns, got ...")
+    }
+
+    #[test]
+    fn test_recording_session() {
+        let mut session = MovieSession::Recording { movie: Movie::new("test"), path: PathBuf::new() };
+        let mut frame = MovieFrame::default();
+        frame.ports[1] = ButtonState::START;
+        assert_eq!(session.next_frame(frame), frame);
+        match session {
+            MovieSession::Recording { movie, .. } => assert_eq!(movie.frames, vec![frame]),
+            _ => unreachable!()
+        }
+    }
+
+    #[test]
+    fn test_rewind_session() {
+        let mut recording = MovieSession::Recording { movie: Movie::new("test"), path: PathBuf::new() };
+        for _ in 0..3 {
+            recording.next_frame(MovieFrame::default());
+        }
+        recording.rewind_to(1);
+        match recording {
+            MovieSession::Recording { movie, .. } => assert_eq!(movie.frames.len(), 1),
+            _ => unreachable!()
+        }
+        let movie = Movie::parse(FM2).unwrap();
+        let second = movie.frames[1];
+        let mut playing = MovieSession::Playing { movie, frame: 0 };
+        for _ in 0..3 {
+            playing.next_frame(MovieFrame::default());
+        }
+        playing.rewind_to(1);
+        assert_eq!(playing.next_frame(MovieFrame::default()), second)
+    }
+
+    // Counts resets at $12 and keeps copying pad 1 into $11
+    fn create_input_rom() -> Vec<u8> {
+        let program = assemble("
+                    INC $12
+            poll:   LDA #1
+                    STA $4016
+                    LDA #0
+                    STA $4016
+                    LDX #8
+            read:   LDA $4016
+                    LSR A
+                    ROR $10
+                    DEX
+                    BNE read
+                    LDA $10
+                    STA $11
+                    JMP poll
+            nmi:    RTI", 0x8000).unwrap();
+        let mut payload = vec![0x4E, 0x45, 0x53, 0x1A, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let mut prg_rom = vec![0; 0x4000];
+        prg_rom[..program.len()].copy_from_slice(&program);
+        // The NMI vector points at the RTI, reset at $8000
+        prg_rom[0x3FFA] = (program.len() - 1) as u8;
+        prg_rom[0x3FFB] = 0x80;
+        prg_rom[0x3FFD] = 0x80;
+        payload.append(&mut prg_rom);
+        payload
+    }
+
+    #[test]
+    fn test_play_movie() {
+        let movie = Movie::parse(FM2).unwrap();
+        let mut cpu = Console::boot(CartridgeLoader::load_cartridge(create_input_rom()));
+        play_movie(&mut cpu, &Movie { header: vec![], frames: movie.frames[..2].to_vec() });
+        assert_eq!(cpu.bus().peek(0x11), (ButtonState::RIGHT | ButtonState::A).bits());
+        assert_eq!(cpu.bus().peek(0x12), 1);
+        play_movie(&mut cpu, &Movie { header: vec![], frames: movie.frames[2..].to_vec() });
+        assert_eq!(cpu.bus().peek(0x11), (ButtonState::LEFT | ButtonState::B).bits());
+        assert_eq!(cpu.bus().peek(0x12), 2)
+    }
+}
@@ -1,4 +1,337 @@
-use crate::addressing::Addressing;
+use crate::addressing::{Addressing, AddressingRegistry};
+use std::fmt;
+
+use self::Mnemonic::*;
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Mnemonic {
+    ADC, AHX, ALR, ANC, AND, ARR, ASL, AXS, BCC, BCS, BEQ, BIT,
+    BMI, BNE, BPL, BRK, BVC, BVS, CLC, CLD, CLI, CLV, CMP, CPX,
+    CPY, DCP, DEC, DEX, DEY, EOR, INC, INX, INY, ISB, JAM, JMP,
+    JSR, LAS, LAX, LDA, LDX, LDY, LSR, LXA, NOP, ORA, PHA, PHP,
+    PLA, PLP, RLA, ROL, ROR, RRA, RTI, RTS, SAX, SBC, SEC, SED,
+    SEI, SHX, SHY, SLO, SRE, STA, STX, STY, TAS, TAX, TAY, TSX,
+    TXA, TXS, TYA, XAA,
+}
+
+impl fmt::Display for Mnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct OpCodeInfo {
+    pub mnemonic: Mnemonic,
+    pub addressing: Addressing,
+    pub bytes: u8,
+    pub cycles: u8,
+    pub page_cross_penalty: bool,
+    pub official: bool
+}
+
+const IMP: Addressing = Addressing::implied();
+const ACC: Addressing = Addressing::accumulator();
+const IMM: Addressing = Addressing::immediate();
+const ZP0: Addressing = Addressing::zero_page();
+const ZPX: Addressing = Addressing::zero_page_indexed(AddressingRegistry::X);
+const ZPY: Addressing = Addressing::zero_page_indexed(AddressingRegistry::Y);
+const ABS: Addressing = Addressing::absolute();
+const ABX: Addressing = Addressing::absolute_indexed(AddressingRegistry::X);
+const ABY: Addressing = Addressing::absolute_indexed(AddressingRegistry::Y);
+const IND: Addressing = Addressing::indirect();
+const IZX: Addressing = Addressing::indexed_indirect();
+const IZY: Addressing = Addressing::indirect_indexed();
+const REL: Addressing = Addressing::relative();
+
+const fn entry(mnemonic: Mnemonic, addressing: Addressing, cycles: u8, page_cross_penalty: bool, official: bool) -> OpCodeInfo {
+    OpCodeInfo {
+        mnemonic,
+        addressing,
+        bytes: addressing.bytes(),
+        cycles,
+        page_cross_penalty,
+        official
+    }
+}
+
+const fn official(mnemonic: Mnemonic, addressing: Addressing, cycles: u8) -> OpCodeInfo {
+    entry(mnemonic, addressing, cycles, false, true)
+}
+
+// Takes an additional cycle when indexing crosses a page boundary
+const fn official_paged(mnemonic: Mnemonic, addressing: Addressing, cycles: u8) -> OpCodeInfo {
+    entry(mnemonic, addressing, cycles, true, true)
+}
+
+const fn unofficial(mnemonic: Mnemonic, addressing: Addressing, cycles: u8) -> OpCodeInfo {
+    entry(mnemonic, addressing, cycles, false, false)
+}
+
+const fn unofficial_paged(mnemonic: Mnemonic, addressing: Addressing, cycles: u8) -> OpCodeInfo {
+    entry(mnemonic, addressing, cycles, true, false)
+}
+
+// Branches are listed with their not-taken timing, the CPU adds the taken and page cross cycles
+pub static OP_CODE_TABLE: [OpCodeInfo; 256] = [
+    /* 0x00 */ official(BRK, IMP, 7),
+    /* 0x01 */ official(ORA, IZX, 6),
+    /* 0x02 */ unofficial(JAM, IMP, 2),
+    /* 0x03 */ unofficial(SLO, IZX, 8),
+    /* 0x04 */ unofficial(NOP, ZP0, 3),
+    /* 0x05 */ official(ORA, ZP0, 3),
+    /* 0x06 */ official(ASL, ZP0, 5),
+    /* 0x07 */ unofficial(SLO, ZP0, 5),
+    /* 0x08 */ official(PHP, IMP, 3),
+    /* 0x09 */ official(ORA, IMM, 2),
+    /* 0x0A */ official(ASL, ACC, 2),
+    /* 0x0B */ unofficial(ANC, IMM, 2),
+    /* 0x0C */ unofficial(NOP, ABS, 4),
+    /* 0x0D */ official(ORA, ABS, 4),
+    /* 0x0E */ official(ASL, ABS, 6),
+    /* 0x0F */ unofficial(SLO, ABS, 6),
+    /* 0x10 */ official(BPL, REL, 2),
+    /* 0x11 */ official_paged(ORA, IZY, 5),
+    /* 0x12 */ unofficial(JAM, IMP, 2),
+    /* 0x13 */ unofficial(SLO, IZY, 8),
+    /* 0x14 */ unofficial(NOP, ZPX, 4),
+    /* 0x15 */ official(ORA, ZPX, 4),
+    /* 0x16 */ official(ASL, ZPX, 6),
+    /* 0x17 */ unofficial(SLO, ZPX, 6),
+    /* 0x18 */ official(CLC, IMP, 2),
+    /* 0x19 */ official_paged(ORA, ABY, 4),
+    /* 0x1A */ unofficial(NOP, IMP, 2),
+    /* 0x1B */ unofficial(SLO, ABY, 7),
+    /* 0x1C */ unofficial_paged(NOP, ABX, 4),
+    /* 0x1D */ official_paged(ORA, ABX, 4),
+    /* 0x1E */ official(ASL, ABX, 7),
+    /* 0x1F */ unofficial(SLO, ABX, 7),
+    /* 0x20 */ official(JSR, ABS, 6),
+    /* 0x21 */ official(AND, IZX, 6),
+    /* 0x22 */ unofficial(JAM, IMP, 2),
+    /* 0x23 */ unofficial(RLA, IZX, 8),
+    /* 0x24 */ official(BIT, ZP0, 3),
+    /* 0x25 */ official(AND, ZP0, 3),
+    /* 0x26 */ official(ROL, ZP0, 5),
+    /* 0x27 */ unofficial(RLA, ZP0, 5),
+    /* 0x28 */ official(PLP, IMP, 4),
+    /* 0x29 */ official(AND, IMM, 2),
+    /* 0x2A */ official(ROL, ACC, 2),
+    /* 0x2B */ unofficial(ANC, IMM, 2),
+    /* 0x2C */ official(BIT, ABS, 4),
+    /* 0x2D */ official(AND, ABS, 4),
+    /* 0x2E */ official(ROL, ABS, 6),
+    /* 0x2F */ unofficial(RLA, ABS, 6),
+    /* 0x30 */ official(BMI, REL, 2),
+    /* 0x31 */ official_paged(AND, IZY, 5),
+    /* 0x32 */ unofficial(JAM, IMP, 2),
+    /* 0x33 */ unofficial(RLA, IZY, 8),
+    /* 0x34 */ unofficial(NOP, ZPX, 4),
+    /* 0x35 */ official(AND, ZPX, 4),
+    /* 0x36 */ official(ROL, ZPX, 6),
+    /* 0x37 */ unofficial(RLA, ZPX, 6),
+    /* 0x38 */ official(SEC, IMP, 2),
+    /* 0x39 */ official_paged(AND, ABY, 4),
+    /* 0x3A */ unofficial(NOP, IMP, 2),
+    /* 0x3B */ unofficial(RLA, ABY, 7),
+    /* 0x3C */ unofficial_paged(NOP, ABX, 4),
+    /* 0x3D */ official_paged(AND, ABX, 4),
+    /* 0x3E */ official(ROL, ABX, 7),
+    /* 0x3F */ unofficial(RLA, ABX, 7),
+    /* 0x40 */ official(RTI, IMP, 6),
+    /* 0x41 */ official(EOR, IZX, 6),
+    /* 0x42 */ unofficial(JAM, IMP, 2),
+    /* 0x43 */ unofficial(SRE, IZX, 8),
+    /* 0x44 */ unofficial(NOP, ZP0, 3),
+    /* 0x45 */ official(EOR, ZP0, 3),
+    /* 0x46 */ official(LSR, ZP0, 5),
+    /* 0x47 */ unofficial(SRE, ZP0, 5),
+    /* 0x48 */ official(PHA, IMP, 3),
+    /* 0x49 */ official(EOR, IMM, 2),
+    /* 0x4A */ official(LSR, ACC, 2),
+    /* 0x4B */ unofficial(ALR, IMM, 2),
+    /* 0x4C */ official(JMP, ABS, 3),
+    /* 0x4D */ official(EOR, ABS, 4),
+    /* 0x4E */ official(LSR, ABS, 6),
+    /* 0x4F */ unofficial(SRE, ABS, 6),
+    /* 0x50 */ official(BVC, REL, 2),
+    /* 0x51 */ official_paged(EOR, IZY, 5),
+    /* 0x52 */ unofficial(JAM, IMP, 2),
+    /* 0x53 */ unofficial(SRE, IZY, 8),
+    /* 0x54 */ unofficial(NOP, ZPX, 4),
+    /* 0x55 */ official(EOR, ZPX, 4),
+    /* 0x56 */ official(LSR, ZPX, 6),
+    /* 0x57 */ unofficial(SRE, ZPX, 6),
+    /* 0x58 */ official(CLI, IMP, 2),
+    /* 0x59 */ official_paged(EOR, ABY, 4),
+    /* 0x5A */ unofficial(NOP, IMP, 2),
+    /* 0x5B */ unofficial(SRE, ABY, 7),
+    /* 0x5C */ unofficial_paged(NOP, ABX, 4),
+    /* 0x5D */ official_paged(EOR, ABX, 4),
+    /* 0x5E */ official(LSR, ABX, 7),
+    /* 0x5F */ unofficial(SRE, ABX, 7),
+    /* 0x60 */ official(RTS, IMP, 6),
+    /* 0x61 */ official(ADC, IZX, 6),
+    /* 0x62 */ unofficial(JAM, IMP, 2),
+    /* 0x63 */ unofficial(RRA, IZX, 8),
+    /* 0x64 */ unofficial(NOP, ZP0, 3),
+    /* 0x65 */ official(ADC, ZP0, 3),
+    /* 0x66 */ official(ROR, ZP0, 5),
+    /* 0x67 */ unofficial(RRA, ZP0, 5),
+    /* 0x68 */ official(PLA, IMP, 4),
+    /* 0x69 */ official(ADC, IMM, 2),
+    /* 0x6A */ official(ROR, ACC, 2),
+    /* 0x6B */ unofficial(ARR, IMM, 2),
+    /* 0x6C */ official(JMP, IND, 5),
+    /* 0x6D */ official(ADC, ABS, 4),
+    /* 0x6E */ official(ROR, ABS, 6),
+    /* 0x6F */ unofficial(RRA, ABS, 6),
+    /* 0x70 */ official(BVS, REL, 2),
+    /* 0x71 */ official_paged(ADC, IZY, 5),
+    /* 0x72 */ unofficial(JAM, IMP, 2),
+    /* 0x73 */ unofficial(RRA, IZY, 8),
+    /* 0x74 */ unofficial(NOP, ZPX, 4),
+    /* 0x75 */ official(ADC, ZPX, 4),
+    /* 0x76 */ official(ROR, ZPX, 6),
+    /* 0x77 */ unofficial(RRA, ZPX, 6),
+    /* 0x78 */ official(SEI, IMP, 2),
+    /* 0x79 */ official_paged(ADC, ABY, 4),
+    /* 0x7A */ unofficial(NOP, IMP, 2),
+    /* 0x7B */ unofficial(RRA, ABY, 7),
+    /* 0x7C */ unofficial_paged(NOP, ABX, 4),
+    /* 0x7D */ official_paged(ADC, ABX, 4),
+    /* 0x7E */ official(ROR, ABX, 7),
+    /* 0x7F */ unofficial(RRA, ABX, 7),
+    /* 0x80 */ unofficial(NOP, IMM, 2),
+    /* 0x81 */ official(STA, IZX, 6),
+    /* 0x82 */ unofficial(NOP, IMM, 2),
+    /* 0x83 */ unofficial(SAX, IZX, 6),
+    /* 0x84 */ official(STY, ZP0, 3),
+    /* 0x85 */ official(STA, ZP0, 3),
+    /* 0x86 */ official(STX, ZP0, 3),
+    /* 0x87 */ unofficial(SAX, ZP0, 3),
+    /* 0x88 */ official(DEY, IMP, 2),
+    /* 0x89 */ unofficial(NOP, IMM, 2),
+    /* 0x8A */ official(TXA, IMP, 2),
+    /* 0x8B */ unofficial(XAA, IMM, 2),
+    /* 0x8C */ official(STY, ABS, 4),
+    /* 0x8D */ official(STA, ABS, 4),
+    /* 0x8E */ official(STX, ABS, 4),
+    /* 0x8F */ unofficial(SAX, ABS, 4),
+    /* 0x90 */ official(BCC, REL, 2),
+    /* 0x91 */ official(STA, IZY, 6),
+    /* 0x92 */ unofficial(JAM, IMP, 2),
+    /* 0x93 */ unofficial(AHX, IZY, 6),
+    /* 0x94 */ official(STY, ZPX, 4),
+    /* 0x95 */ official(STA, ZPX, 4),
+    /* 0x96 */ official(STX, ZPY, 4),
+    /* 0x97 */ unofficial(SAX, ZPY, 4),
+    /* 0x98 */ official(TYA, IMP, 2),
+    /* 0x99 */ official(STA, ABY, 5),
+    /* 0x9A */ official(TXS, IMP, 2),
+    /* 0x9B */ unofficial(TAS, ABY, 5),
+    /* 0x9C */ unofficial(SHY, ABX, 5),
+    /* 0x9D */ official(STA, ABX, 5),
+    /* 0x9E */ unofficial(SHX, ABY, 5),
+    /* 0x9F */ unofficial(AHX, ABY, 5),
+    /* 0xA0 */ official(LDY, IMM, 2),
+    /* 0xA1 */ official(LDA, IZX, 6),
+    /* 0xA2 */ official(LDX, IMM, 2),
+    /* 0xA3 */ unofficial(LAX, IZX, 6),
+    /* 0xA4 */ official(LDY, ZP0, 3),
+    /* 0xA5 */ official(LDA, ZP0, 3),
+    /* 0xA6 */ official(LDX, ZP0, 3),
+    /* 0xA7 */ unofficial(LAX, ZP0, 3),
+    /* 0xA8 */ official(TAY, IMP, 2),
+    /* 0xA9 */ official(LDA, IMM, 2),
+    /* 0xAA */ official(TAX, IMP, 2),
+    /* 0xAB */ unofficial(LXA, IMM, 2),
+    /* 0xAC */ official(LDY, ABS, 4),
+    /* 0xAD */ official(LDA, ABS, 4),
+    /* 0xAE */ official(LDX, ABS, 4),
+    /* 0xAF */ unofficial(LAX, ABS, 4),
+    /* 0xB0 */ official(BCS, REL, 2),
+    /* 0xB1 */ official_paged(LDA, IZY, 5),
+    /* 0xB2 */ unofficial(JAM, IMP, 2),
+    /* 0xB3 */ unofficial_paged(LAX, IZY, 5),
+    /* 0xB4 */ official(LDY, ZPX, 4),
+    /* 0xB5 */ official(LDA, ZPX, 4),
+    /* 0xB6 */ official(LDX, ZPY, 4),
+    /* 0xB7 */ unofficial(LAX, ZPY, 4),
+    /* 0xB8 */ official(CLV, IMP, 2),
+    /* 0xB9 */ official_paged(LDA, ABY, 4),
+    /* 0xBA */ official(TSX, IMP, 2),
+    /* 0xBB */ unofficial_paged(LAS, ABY, 4),
+    /* 0xBC */ official_paged(LDY, ABX, 4),
+    /* 0xBD */ official_paged(LDA, ABX, 4),
+    /* 0xBE */ official_paged(LDX, ABY, 4),
+    /* 0xBF */ unofficial_paged(LAX, ABY, 4),
+    /* 0xC0 */ official(CPY, IMM, 2),
+    /* 0xC1 */ official(CMP, IZX, 6),
+    /* 0xC2 */ unofficial(NOP, IMM, 2),
+    /* 0xC3 */ unofficial(DCP, IZX, 8),
+    /* 0xC4 */ official(CPY, ZP0, 3),
+    /* 0xC5 */ official(CMP, ZP0, 3),
+    /* 0xC6 */ official(DEC, ZP0, 5),
+    /* 0xC7 */ unofficial(DCP, ZP0, 5),
+    /* 0xC8 */ official(INY, IMP, 2),
+    /* 0xC9 */ official(CMP, IMM, 2),
+    /* 0xCA */ official(DEX, IMP, 2),
+    /* 0xCB */ unofficial(AXS, IMM, 2),
+    /* 0xCC */ official(CPY, ABS, 4),
+    /* 0xCD */ official(CMP, ABS, 4),
+    /* 0xCE */ official(DEC, ABS, 6),
+    /* 0xCF */ unofficial(DCP, ABS, 6),
+    /* 0xD0 */ official(BNE, REL, 2),
+    /* 0xD1 */ official_paged(CMP, IZY, 5),
+    /* 0xD2 */ unofficial(JAM, IMP, 2),
+    /* 0xD3 */ unofficial(DCP, IZY, 8),
+    /* 0xD4 */ unofficial(NOP, ZPX, 4),
+    /* 0xD5 */ official(CMP, ZPX, 4),
+    /* 0xD6 */ official(DEC, ZPX, 6),
+    /* 0xD7 */ unofficial(DCP, ZPX, 6),
+    /* 0xD8 */ official(CLD, IMP, 2),
+    /* 0xD9 */ official_paged(CMP, ABY, 4),
+    /* 0xDA */ unofficial(NOP, IMP, 2),
+    /* 0xDB */ unofficial(DCP, ABY, 7),
+    /* 0xDC */ unofficial_paged(NOP, ABX, 4),
+    /* 0xDD */ official_paged(CMP, ABX, 4),
+    /* 0xDE */ official(DEC, ABX, 7),
+    /* 0xDF */ unofficial(DCP, ABX, 7),
+    /* 0xE0 */ official(CPX, IMM, 2),
+    /* 0xE1 */ official(SBC, IZX, 6),
+    /* 0xE2 */ unofficial(NOP, IMM, 2),
+    /* 0xE3 */ unofficial(ISB, IZX, 8),
+    /* 0xE4 */ official(CPX, ZP0, 3),
+    /* 0xE5 */ official(SBC, ZP0, 3),
+    /* 0xE6 */ official(INC, ZP0, 5),
+    /* 0xE7 */ unofficial(ISB, ZP0, 5),
+    /* 0xE8 */ official(INX, IMP, 2),
+    /* 0xE9 */ official(SBC, IMM, 2),
+    /* 0xEA */ official(NOP, IMP, 2),
+    /* 0xEB */ unofficial(SBC, IMM, 2),
+    /* 0xEC */ official(CPX, ABS, 4),
+    /* 0xED */ official(SBC, ABS, 4),
+    /* 0xEE */ official(INC, ABS, 6),
+    /* 0xEF */ unofficial(ISB, ABS, 6),
+    /* 0xF0 */ official(BEQ, REL, 2),
+    /* 0xF1 */ official_paged(SBC, IZY, 5),
+    /* 0xF2 */ unofficial(JAM, IMP, 2),
+    /* 0xF3 */ unofficial(ISB, IZY, 8),
+    /* 0xF4 */ unofficial(NOP, ZPX, 4),
+    /* 0xF5 */ official(SBC, ZPX, 4),
+    /* 0xF6 */ official(INC, ZPX, 6),
+    /* 0xF7 */ unofficial(ISB, ZPX, 6),
+    /* 0xF8 */ official(SED, IMP, 2),
+    /* 0xF9 */ official_paged(SBC, ABY, 4),
+    /* 0xFA */ unofficial(NOP, IMP, 2),
+    /* 0xFB */ unofficial(ISB, ABY, 7),
+    /* 0xFC */ unofficial_paged(NOP, ABX, 4),
+    /* 0xFD */ official_paged(SBC, ABX, 4),
+    /* 0xFE */ official(INC, ABX, 7),
+    /* 0xFF */ unofficial(ISB, ABX, 7),
+
+];
 
 pub struct OpCode {
     pub value: u8,
@@ -10,21 +343,49 @@ impl OpCode {
         OpCode { value }
     }
 
-    pub fn upper_op_code(&self) -> u8 {
-        (self.value & 0b1110_0000) >> 5
-    }
-
-    pub fn mid_op_code(&self) -> u8 {
-        (self.value & 0b0001_1100) >> 2
-    }
-
-    pub fn lower_op_code(&self) -> u8 {
-        self.value & 0b0000_0011
+    pub fn info(&self) -> &'static OpCodeInfo {
+        &OP_CODE_TABLE[self.value as usize]
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::addressing::AddressingMode;
+
+    #[test]
+    fn test_official_op_code_count() {
+        let official = OP_CODE_TABLE.iter().filter(|info| info.official).count();
+        assert_eq!(official, 151)
+    }
+
+    #[test]
+    fn test_op_code_info() {
+        let info = OpCode::new(0xBD).info();
+        assert_eq!(info.mnemonic, LDA);
+        assert_eq!(info.addressing.mode, AddressingMode::AbsoluteIndexed);
+        assert_eq!(info.addressing.register, Some(AddressingRegistry::X));
+        assert_eq!(info.bytes, 3);
+        assert_eq!(info.cycles, 4);
+        assert!(info.page_cross_penalty);
+        assert!(info.official);
 
-}
\ No newline at end of file
+        let info = OpCode::new(0xB7).info();
+        assert_eq!(info.mnemonic, LAX);
+        assert_eq!(info.addressing.register, Some(AddressingRegistry::Y));
+        assert_eq!(info.bytes, 2);
+        assert!(!info.official)
+    }
+
+    #[test]
+    fn test_bit_fields_agree_with_table() {
+        // Group one instructions (c == 01) are laid out as aaabbbcc
+        for value in (0..=0xFF).filter(|value| value & 0b11 == 0b01) {
+            let op_code = OpCode::new(value);
+            let expected = [ORA, AND, EOR, ADC, STA, LDA, CMP, SBC][(op_code.value >> 5) as usize];
+            if value != 0x89 {
+                assert_eq!(op_code.info().mnemonic, expected, "{:#04X}", value);
+            }
+        }
+    }
+}
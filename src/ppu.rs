@@ -1,221 +1,443 @@
-use crate::screen::Screen;
-use crate::util::{nth_bit, combine_u8};
+use crate::util::nth_bit;
 
 use log::{info, warn};
 use std::num::Wrapping;
 use crate::ppu::NameTableMirroring::{HORIZONTAL, VERTICAL};
+use crate::save_state::{Snapshot, StateReader, StateWriter};
 
-static PPU_ADDRESSABLE_RANGE: u16 = 0x3FF;
-
-static PALETTE: &'static [(u8, u8, u8)] = &[
+static PALETTE: &[(u8, u8, u8)] = &[
     (84,  84,  84),    (0,  30, 116),   ( 8,  16, 144),   (48,   0, 136),   (68,   0, 100),   (92,   0,  48),   (84,   4,   0),   (60,  24,   0),   (32,  42,   0),   ( 8,  58,   0),   ( 0,  64,   0),   ( 0,  60,   0),   ( 0,  50,  60),   ( 0,   0,   0), (0, 0, 0), (0, 0, 0),
     (152, 150, 152),    (8,  76, 196),  ( 48,  50, 236),  ( 92,  30, 228),  (136,  20, 176),  (160,  20, 100),  (152,  34,  32),  (120,  60,   0),  ( 84,  90,   0),  ( 40, 114,   0),  (  8, 124,   0),  (  0, 118,  40),  (  0, 102, 120),  (  0,   0,   0), (0, 0, 0), (0, 0, 0),
     (236, 238, 236),   (76, 154, 236),  (120, 124, 236),  (176,  98, 236),  (228,  84, 236),  (236,  88, 180),  (236, 106, 100),  (212, 136,  32),  (160, 170,   0),  (116, 196,   0),  ( 76, 208,  32),  ( 56, 204, 108),  ( 56, 180, 204),  ( 60,  60,  60), (0, 0, 0), (0, 0, 0),
     (236, 238, 236),  (168, 204, 236),  (188, 188, 236),  (212, 178, 236),  (236, 174, 236),  (236, 174, 212),  (236, 180, 176),  (228, 196, 144),  (204, 210, 120),  (180, 222, 120),  (168, 226, 144),  (152, 226, 180),  (160, 214, 228),  (160, 162, 160), (0, 0, 0), (0, 0, 0),
 ];
 
-static STARTUP_CYCLES: u64 = 1_000_000;
+pub static FRAME_WIDTH: usize = 256;
+pub static FRAME_HEIGHT: usize = 240;
+
+// Bits of the I/O latch fade to zero about 600ms (36 frames) after they were last driven
+static OPEN_BUS_DECAY_CYCLES: u64 = 36 * 341 * 262;
 
 #[derive(Clone, Copy, Debug)]
 pub enum NameTableMirroring {
     HORIZONTAL, VERTICAL
 }
 
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug, Copy, PartialEq)]
 pub struct Colour {
     pub(crate) r: u8,
     pub(crate) g: u8,
     pub(crate) b: u8
 }
 
+// Sprite fetched for the line being drawn
+#[derive(Clone, Copy, Debug)]
+struct LineSprite {
+    x: u8,
+    attributes: u8,
+    pattern_low: u8,
+    pattern_high: u8
+}
+
 #[derive(Debug)]
 pub struct Ppu {
     ram: Vec<u8>,
     cycles: u16,
     scanline: u16,
     ppu_status: u8,
+    // Loopy registers, v is the current VRAM address laid out as yyy NN YYYYY XXXXX, t the address
+    // the next frame or line starts from, x the fine horizontal scroll and w the first/second write toggle
     vram_address: u16,
+    temp_address: u16,
+    fine_x: u8,
+    write_toggle: bool,
     nametable_mirroring: NameTableMirroring,
     latch: u8,
+    latch_refreshed: [u64; 8],
     last_register: u8,
     pub nmi_occurred: bool,
     status: u8,
-    current_pixel: u16,
-    frame: Vec<(u16, u16, Colour)>,
+    mask: u8,
+    next_tile: u8,
+    next_attribute: u8,
+    next_pattern_low: u8,
+    next_pattern_high: u8,
+    // The tile being drawn sits in the high byte, the next one in the low byte
+    pattern_shift_low: u16,
+    pattern_shift_high: u16,
+    attribute_shift_low: u16,
+    attribute_shift_high: u16,
+    frame: Vec<Colour>,
     internal_buffer: u8,
     oam: Vec<u8>,
     oam_address: u8,
-    total_cycles: u64
+    secondary_oam: Vec<u8>,
+    sprite_count: usize,
+    sprite_zero_next: bool,
+    line_sprites: Vec<LineSprite>,
+    sprite_zero_line: bool,
+    total_cycles: u64,
+    frame_count: u64
 }
 
 impl Ppu {
     pub fn new(mut chr_rom: Vec<u8>, mirroring: NameTableMirroring) -> Ppu {
-        chr_rom.append(vec![0 as u8; 0x1FFF].as_mut());
-        return Ppu {
+        // Pattern tables, nametables and palettes share one buffer indexed by the PPU address
+        let size = chr_rom.len().max(0x2000) + 0x2000;
+        chr_rom.resize(size, 0);
+        Ppu {
             cycles: 0,
             scanline: 0,
             ppu_status: 0,
             ram: chr_rom,
             nametable_mirroring: mirroring,
             latch: 0,
+            latch_refreshed: [0; 8],
             vram_address: 0,
+            temp_address: 0,
+            fine_x: 0,
+            write_toggle: false,
             last_register: 0,
             nmi_occurred: false,
             status: 0,
-            current_pixel: 0,
-            frame: Ppu::new_frame(),
+            mask: 0,
+            next_tile: 0,
+            next_attribute: 0,
+            next_pattern_low: 0,
+            next_pattern_high: 0,
+            pattern_shift_low: 0,
+            pattern_shift_high: 0,
+            attribute_shift_low: 0,
+            attribute_shift_high: 0,
+            frame: vec![Colour{r: 0, g: 0, b: 0}; FRAME_WIDTH * FRAME_HEIGHT],
             internal_buffer: 0,
-            oam: vec![0 as u8; 256],
+            oam: vec![0; 256],
             oam_address: 0,
-            total_cycles: 0
+            secondary_oam: vec![0xFF; 32],
+            sprite_count: 0,
+            sprite_zero_next: false,
+            line_sprites: vec![],
+            sprite_zero_line: false,
+            total_cycles: 0,
+            frame_count: 0
         }
     }
 
-    fn new_frame() -> Vec<(u16, u16, Colour)> {
-        vec![(0, 0, Colour{r: 0, g: 0, b: 0}); 960]
+    pub fn scanline(&self) -> u16 {
+        self.scanline
     }
 
-    pub fn tick(&mut self, screen: &mut Screen) {
+    pub fn dot(&self) -> u16 {
+        self.cycles
+    }
+
+    // Frames finished since power on, counted when vblank starts
+    pub fn frame(&self) -> u64 {
+        self.frame_count
+    }
+
+    // Pixels of the last finished frame, row by row
+    pub fn frame_buffer(&self) -> &[Colour] {
+        &self.frame
+    }
+
+    pub fn tick(&mut self) {
         if self.get_nmi_output() && self.nmi_occurred {
             info!("[PPU]: NMI OCCURRED");
         }
         self.cycles += 1;
         self.total_cycles = (Wrapping(self.total_cycles) + Wrapping(1)).0;
 
+        // With rendering on, odd frames skip the last dot of the pre-render line
+        if (self.scanline == 261) && (self.cycles == 340) && self.rendering_enabled() && (self.frame_count % 2 == 1) {
+            self.cycles = 341;
+        }
+
         if self.cycles == 341 {
-            self.scanline += 1;
+            self.scanline = (self.scanline + 1) % 262;
             self.cycles = 0;
         }
 
-        if (0 <= self.scanline) && (self.scanline <= 239) {
-
+        // Visible lines and the pre-render line fetch the background, only visible lines output pixels
+        if (self.scanline <= 239) || (self.scanline == 261) {
+            self.render_dot();
         }
 
         if (self.scanline == 261) && (self.cycles == 1) {
-            self.scanline = 0;
-            self.clear_vblank();
+            // Vblank, sprite zero hit and sprite overflow all end on the pre-render line
+            self.ppu_status &= 0b0001_1111;
             self.nmi_occurred = false;
         } else if (self.scanline == 241) && (self.cycles == 1) {
             info!("Setting vblank, nmi output: {}", self.get_nmi_output());
             self.set_vblank();
             self.nmi_occurred = true;
+            self.frame_count += 1;
         }
     }
 
-    fn draw(&mut self, screen: &mut Screen) {
-        if self.total_cycles > STARTUP_CYCLES {
-            self.draw_tile(screen);
-            if self.current_pixel >= 960 {
-                self.current_pixel = 0;
-                self.draw_sprites(screen);
-                screen.draw_pixels(&self.frame);
-                self.frame = Ppu::new_frame();
+    // Fetches on the cycles of the NTSC PPU, every tile takes a nametable, attribute and two pattern reads
+    fn render_dot(&mut self) {
+        let dot = self.cycles;
+        if !self.rendering_enabled() {
+            // Nothing is fetched, the backdrop is shown unless v points into the palettes, then that entry is
+            if (self.scanline <= 239) && (1..=256).contains(&dot) {
+                let address = match self.get_vram_address() as u16 {
+                    address @ 0x3F00..=0x3FFF => self.vram_index(address) as u16,
+                    _ => 0x3F00
+                };
+                self.frame[self.scanline as usize * FRAME_WIDTH + dot as usize - 1] = self.palette_colour(address);
+            }
+            return
+        }
+        if (2..=257).contains(&dot) || (321..=337).contains(&dot) {
+            self.shift_background();
+            match (dot - 1) % 8 {
+                0 => {
+                    self.load_background_shifters();
+                    self.next_tile = self.fetch_nametable();
+                },
+                2 => self.next_attribute = self.fetch_attribute(),
+                4 => self.next_pattern_low = self.fetch_pattern(0),
+                6 => self.next_pattern_high = self.fetch_pattern(8),
+                7 => self.increment_coarse_x(),
+                _ => {}
+            }
+        }
+        // Unused nametable fetches at the end of the line
+        if (dot == 338) || (dot == 340) {
+            self.next_tile = self.fetch_nametable();
+        }
+        if dot == 256 {
+            self.increment_y();
+        }
+        if dot == 257 {
+            self.vram_address = (self.vram_address & !0x041F) | (self.temp_address & 0x041F);
+        }
+        if (self.scanline == 261) && (280..=304).contains(&dot) {
+            self.vram_address = (self.vram_address & !0x7BE0) | (self.temp_address & 0x7BE0);
+        }
+        if (self.scanline <= 239) && (1..=256).contains(&dot) {
+            self.output_pixel(dot - 1);
+        }
+        // Sprites for the next line, nothing is evaluated for line 0 on the pre-render line
+        if dot == 257 {
+            if self.scanline == 261 {
+                self.sprite_count = 0;
+                self.sprite_zero_next = false;
+            } else {
+                self.evaluate_sprites();
             }
         }
+        if dot == 320 {
+            self.fetch_sprites();
+        }
     }
 
-    pub fn get_sprite_color(&mut self, palette_idx: u8) -> Vec<u16> {
-        let background: u16 = 0x3F00;
-        let palette_increment: u16 = (0x11 + (4 * (palette_idx))) as u16;
-        return Vec::from([background, background + palette_increment, background + palette_increment + 1, background + palette_increment + 2]);
+    fn fetch_nametable(&self) -> u8 {
+        self.ram[self.vram_index(0x2000 | (self.vram_address & 0x0FFF))]
     }
 
-    pub fn draw_sprites(&mut self, screen: &mut Screen) {
-        for i in (0..self.oam.len()).step_by(4) {
-            let tile_y = self.oam[i] as u16;
-            let tile_idx = self.oam[i + 1] as u16;
-            let attributes = self.oam[i + 2];
-            let tile_x = self.oam[i + 3] as u16;
-
-            let flip_horizontal = nth_bit(attributes, 6);
-            let flip_vertical = nth_bit(attributes, 7);
-
-            let palette_idx = 0b11 & attributes;
-            let colors = self.get_sprite_color(palette_idx);
+    // One attribute byte covers 4x4 tiles, two bits for each 2x2 quadrant
+    fn fetch_attribute(&self) -> u8 {
+        let address = self.vram_address;
+        let attribute_address = 0x23C0 | (address & 0x0C00) | ((address >> 4) & 0x38) | ((address >> 2) & 0x07);
+        let shift = ((address >> 4) & 0b100) | (address & 0b10);
+        (self.ram[self.vram_index(attribute_address)] >> shift) & 0b11
+    }
 
-            let pattern_table_start = self.get_sprite_pattern_table();
-            let tile = &self.ram[(pattern_table_start + (tile_idx * 16)) as usize..=(pattern_table_start + 15 + (tile_idx * 16)) as usize];
+    // Plane 0 is at offset 0 of the tile and plane 1 at offset 8, one byte per fine y
+    fn fetch_pattern(&mut self, plane: u16) -> u8 {
+        let fine_y = (self.vram_address >> 12) & 0b111;
+        let address = self.get_background_pattern_table() + (self.next_tile as u16 * 16) + plane + fine_y;
+        self.ram[address as usize]
+    }
 
-            if tile_y > 239 { continue };
-            if tile_x > 249 { continue };
+    fn load_background_shifters(&mut self) {
+        self.pattern_shift_low = (self.pattern_shift_low & 0xFF00) | self.next_pattern_low as u16;
+        self.pattern_shift_high = (self.pattern_shift_high & 0xFF00) | self.next_pattern_high as u16;
+        let attribute_low = if nth_bit(self.next_attribute, 0) { 0xFF } else { 0x00 };
+        let attribute_high = if nth_bit(self.next_attribute, 1) { 0xFF } else { 0x00 };
+        self.attribute_shift_low = (self.attribute_shift_low & 0xFF00) | attribute_low;
+        self.attribute_shift_high = (self.attribute_shift_high & 0xFF00) | attribute_high;
+    }
 
-            for x in 0..=7 {
-                let left = tile[x as usize];
-                let right = tile[(x + 8) as usize];
+    fn shift_background(&mut self) {
+        self.pattern_shift_low <<= 1;
+        self.pattern_shift_high <<= 1;
+        self.attribute_shift_low <<= 1;
+        self.attribute_shift_high <<= 1;
+    }
 
-                for y in 0..=7 {
-                    let left_pixel = nth_bit(left, 7 - y);
-                    let right_pixel = nth_bit(right, 7 - y);
+    // Palette RAM address of the pixel leaving the shifters, transparent pixels show the backdrop
+    fn background_pixel(&self) -> u16 {
+        let bit = 0x8000 >> self.fine_x;
+        let pixel = (((self.pattern_shift_high & bit) != 0) as u16) << 1 | ((self.pattern_shift_low & bit) != 0) as u16;
+        let palette = (((self.attribute_shift_high & bit) != 0) as u16) << 1 | ((self.attribute_shift_low & bit) != 0) as u16;
+        if pixel == 0 {
+            0x3F00
+        } else {
+            0x3F00 + (palette * 4) + pixel
+        }
+    }
 
-                    let pixel = ((right_pixel as u8) << 1) | left_pixel as u8;
-                    if pixel == 0 { continue };
-                    let chosen_colour = self.ram[colors[pixel as usize] as usize];
-                    let rgb = PALETTE[chosen_colour as usize];
+    // Coarse x wraps into the horizontally adjacent nametable
+    fn increment_coarse_x(&mut self) {
+        if (self.vram_address & 0x001F) == 31 {
+            self.vram_address &= !0x001F;
+            self.vram_address ^= 0x0400;
+        } else {
+            self.vram_address += 1;
+        }
+    }
 
-                    let (cor_x, cor_y) = match (flip_horizontal, flip_vertical) {
-                        (true, false) => {(tile_x + x as u16, tile_y + y as u16)},
-                        (true, true) => {(tile_x + x as u16, tile_y + y as u16)},
-                        (false, false) => {(tile_x + x as u16, tile_y + y as u16)},
-                        (false, true) => {(tile_x + x as u16, tile_y + y as u16)},
-                    };
-                    self.frame.push((cor_x as u16, cor_y as u16, Colour{r: rgb.0, g: rgb.1, b: rgb.2}))
-                }
-            }
+    // Fine y first, then coarse y, which wraps into the vertically adjacent nametable after row 29
+    fn increment_y(&mut self) {
+        if (self.vram_address & 0x7000) != 0x7000 {
+            self.vram_address += 0x1000;
+            return
         }
+        self.vram_address &= !0x7000;
+        let coarse_y = match (self.vram_address & 0x03E0) >> 5 {
+            29 => {
+                self.vram_address ^= 0x0800;
+                0
+            },
+            // Rows 30 and 31 hold attributes, scrolling into them wraps without switching nametables
+            31 => 0,
+            coarse_y => coarse_y + 1
+        };
+        self.vram_address = (self.vram_address & !0x03E0) | (coarse_y << 5);
     }
 
-    pub fn draw_tile(&mut self, screen: &mut Screen) {
-        let address = self.get_base_nametable_address() + self.current_pixel as u16;
-        let tile_address = self.ram[address as usize] as u16;
-        let pattern_idx = self.get_background_pattern_table() as u16 + (tile_address * 16);
-        let tile = &self.ram[(pattern_idx) as usize..=((pattern_idx) + 15) as usize];
-        let mut tile_row = self.current_pixel % 32 as u16;
-        let mut tile_column = self.current_pixel / 32 as u16;
-        let colours = self.get_background_colour(tile_row, tile_column);
-        let mut cor_x = tile_row * 8;
-        let mut cor_y = tile_column * 8;
+    // Greyscale keeps only the brightness column of the palette, each emphasis bit dims the other two channels
+    fn palette_colour(&self, address: u16) -> Colour {
+        let mut index = self.ram[address as usize] & 0x3F;
+        if nth_bit(self.mask, 0) {
+            index &= 0x30;
+        }
+        let rgb = PALETTE[index as usize];
+        let (emphasis_red, emphasis_green, emphasis_blue) = (nth_bit(self.mask, 5), nth_bit(self.mask, 6), nth_bit(self.mask, 7));
+        let dim = |channel: u8, emphasised: bool| {
+            if (emphasis_red || emphasis_green || emphasis_blue) && !emphasised {
+                (channel as u16 * 13 / 16) as u8
+            } else {
+                channel
+            }
+        };
+        Colour{r: dim(rgb.0, emphasis_red), g: dim(rgb.1, emphasis_green), b: dim(rgb.2, emphasis_blue)}
+    }
 
-        for x in 0..=7 {
-            let left = tile[x as usize];
-            let right = tile[(x + 8) as usize];
+    fn rendering_enabled(&self) -> bool {
+        (self.mask & 0b0001_1000) != 0
+    }
 
-            for y in 0..=7 {
-                let left_pixel = nth_bit(left, 7 - y);
-                let right_pixel = nth_bit(right, 7 - y);
+    // The leftmost 8 pixels have their own switches
+    fn shows_background(&self, x: u16) -> bool {
+        nth_bit(self.mask, 3) && ((x >= 8) || nth_bit(self.mask, 1))
+    }
 
-                let pixel = ((right_pixel as u8) << 1) | left_pixel as u8;
-                let chosen_colour = self.ram[colours[pixel as usize] as usize];
-                let rgb = PALETTE[chosen_colour as usize];
+    fn shows_sprites(&self, x: u16) -> bool {
+        nth_bit(self.mask, 4) && ((x >= 8) || nth_bit(self.mask, 2))
+    }
 
-                let cor_x = cor_x + y as u16;
-                let cor_y = cor_y + x as u16;
-                let tuple = (cor_x, cor_y, Colour{r: rgb.0, g: rgb.1, b: rgb.2});
-                self.frame.push(tuple);
+    // Secondary OAM gets the first eight sprites covering the line, the next line draws them
+    fn evaluate_sprites(&mut self) {
+        self.secondary_oam.iter_mut().for_each(|byte| *byte = 0xFF);
+        self.sprite_count = 0;
+        self.sprite_zero_next = false;
+        let mut sprite = 0;
+        while (sprite < 64) && (self.sprite_count < 8) {
+            if self.sprite_covers_line(self.oam[sprite * 4]) {
+                let slot = self.sprite_count * 4;
+                self.secondary_oam[slot..slot + 4].copy_from_slice(&self.oam[sprite * 4..sprite * 4 + 4]);
+                self.sprite_zero_next |= sprite == 0;
+                self.sprite_count += 1;
             }
+            sprite += 1;
+        }
+        // Past the eighth sprite the hardware steps the byte offset along with the sprite index, so tile,
+        // attribute and x bytes are compared as if they were y and the overflow flag misses or invents sprites
+        let mut offset = 0;
+        while sprite < 64 {
+            if self.sprite_covers_line(self.oam[sprite * 4 + offset]) {
+                self.ppu_status |= 0b0010_0000;
+                break
+            }
+            sprite += 1;
+            offset = (offset + 1) % 4;
         }
-        self.current_pixel += 1;
     }
 
-    fn get_background_colour(&self, tile_row: u16, tile_column: u16) -> Vec<u16> {
-        let attribute_table = self.get_base_nametable_address() + 0x03C0;
-        let attribute_idx = tile_column / 4 * 8 + tile_row / 4;
-        let attribute = self.ram[(attribute_table + attribute_idx) as usize];
+    fn sprite_covers_line(&self, y: u8) -> bool {
+        let row = self.scanline as i16 - y as i16;
+        (0..self.get_sprite_size() as i16).contains(&row)
+    }
 
-        let palette_idx = match(tile_column % 4 / 2, tile_row % 4 / 2) {
-            (0,0) => attribute,
-            (1,0) => (attribute >> 2),
-            (0,1) => (attribute >> 4),
-            (1,1) => (attribute >> 6),
-            _ => panic!("Unknown region")
-        } & 0b11;
-        let background: u16 = 0x3F00;
-        let palette_increment: u16 = 1 + (4 * palette_idx) as u16;
+    // Pattern rows of the evaluated sprites, horizontal flip is applied by reversing the bits and
+    // vertical flip of an 8x16 sprite also swaps its two tiles
+    fn fetch_sprites(&mut self) {
+        let secondary_oam = self.secondary_oam.clone();
+        self.line_sprites.clear();
+        for sprite in secondary_oam.chunks(4).take(self.sprite_count) {
+            let (y, tile, attributes, x) = (sprite[0], sprite[1], sprite[2], sprite[3]);
+            let height = self.get_sprite_size() as u16;
+            // Evaluation used the size at dot 257, PPUCTRL may have shrunk it since so only the low bits count
+            let mut row = (self.scanline - y as u16) & (height - 1);
+            if nth_bit(attributes, 7) {
+                row ^= height - 1;
+            }
+            // 8x16 sprites take the pattern table from bit 0 of the tile, the top half is the even tile
+            let (pattern_table, tile) = if height == 16 {
+                ((tile as u16 & 1) * 0x1000, (tile as u16 & 0xFE) + (row / 8))
+            } else {
+                (self.get_sprite_pattern_table(), tile as u16)
+            };
+            let address = (pattern_table + (tile * 16) + (row % 8)) as usize;
+            let (mut pattern_low, mut pattern_high) = (self.ram[address], self.ram[address + 8]);
+            if nth_bit(attributes, 6) {
+                pattern_low = pattern_low.reverse_bits();
+                pattern_high = pattern_high.reverse_bits();
+            }
+            self.line_sprites.push(LineSprite { x, attributes, pattern_low, pattern_high });
+        }
+        self.sprite_zero_line = self.sprite_zero_next;
+    }
 
-        return Vec::from([background, background + palette_increment, background + palette_increment + 1, background + palette_increment + 2]);
+    // Palette RAM address of the first opaque sprite pixel at x, whether it goes behind the background
+    // and whether it belongs to sprite zero
+    fn sprite_pixel(&self, x: u16) -> Option<(u16, bool, bool)> {
+        for (index, sprite) in self.line_sprites.iter().enumerate() {
+            let column = x.wrapping_sub(sprite.x as u16);
+            if column >= 8 {
+                continue
+            }
+            let bit = 7 - column as u8;
+            let pixel = ((nth_bit(sprite.pattern_high, bit) as u16) << 1) | nth_bit(sprite.pattern_low, bit) as u16;
+            if pixel == 0 {
+                continue
+            }
+            let palette = (sprite.attributes & 0b11) as u16;
+            let sprite_zero = (index == 0) && self.sprite_zero_line;
+            return Some((0x3F10 + (palette * 4) + pixel, nth_bit(sprite.attributes, 5), sprite_zero))
+        }
+        None
     }
 
-    pub fn write_oamdma(&mut self, memory: &[u8]) {
-        self.oam.copy_from_slice(memory);
+    // Opaque pixels of sprite zero over opaque background set the hit flag, except in the last column
+    fn output_pixel(&mut self, x: u16) {
+        let background = if self.shows_background(x) { self.background_pixel() } else { 0x3F00 };
+        let opaque_background = (background & 0b11) != 0;
+        let sprite = if self.shows_sprites(x) { self.sprite_pixel(x) } else { None };
+        let address = match sprite {
+            Some((sprite, behind_background, sprite_zero)) => {
+                if sprite_zero && opaque_background && (x != 255) {
+                    self.ppu_status |= 0b0100_0000;
+                }
+                if behind_background && opaque_background { background } else { sprite }
+            },
+            None => background
+        };
+        self.frame[self.scanline as usize * FRAME_WIDTH + x as usize] = self.palette_colour(address);
     }
 
     fn set_vblank(&mut self) {
@@ -223,73 +445,119 @@ impl Ppu {
     }
 
     fn clear_vblank(&mut self) {
-        self.ppu_status &= 0b0000_0000
-    }
-
-    fn is_vblank(&self) -> bool {
-        (self.ppu_status & 0b1000_0000) != 0
+        self.ppu_status &= 0b0111_1111
     }
 
     pub fn fetch(&mut self, address: u16) -> u8 {
         info!("ppu fetch: {:#01X}", address);
         match address {
-            0x2000 => self.latch, // PPUCTRL
-            0x2001 => self.latch, // PPUMASK
             0x2002 => {
-                let mut result = self.latch;
-                result &= 0b00_01_11_11;
-                if self.is_vblank() {
-//                    warn!("Is vblank");
-                    result |= 0b10_00_00_00;
-                }
+                let result = self.peek(address);
+                self.refresh_latch(result, 0b1110_0000);
                 self.clear_vblank();
-                self.latch = 0;
-                return result
+                self.write_toggle = false;
+                result
             }, // PPUSTATUS
-            0x2003 => self.latch, // OAMADDR
-            0x2004 => self.latch, // OAMDATA
-            0x2005 => self.latch, // PPUSCROLL
-            0x2006 => self.latch, // PPUADDR
+            0x2004 => {
+                let result = self.peek(address);
+                self.refresh_latch(result, 0xFF);
+                result
+            }, // OAMDATA
             0x2007 => {
-                // TODO: Move to func, use in drawing
-                let new_data = match self.vram_address {
-                    0..=0x1FFF => {
-                        let address = self.get_vram_address();
-                        let result = self.ram[address];
-                        self.increment_vram();
-                        warn!("PPU read: {:#01X} from address {:#01X}", result, address);
-                        result
+                let address = self.get_vram_address() as u16;
+                let value = self.ram[self.vram_index(address)];
+                self.increment_vram();
+                warn!("PPU read: {:#01X} from address {:#01X}", value, address);
+                // Everything below the palette goes through the read buffer, palette reads are returned
+                // directly and fill the buffer with the nametable byte underneath
+                let result = match address {
+                    0x3F00..=0x3FFF => {
+                        self.internal_buffer = self.ram[self.vram_index(address & 0x2FFF)];
+                        value
                     },
-                    0x2000..=0x2FFF => {
-                        let mut mirrored_down = self.get_vram_address() & 0x2FFF;
-                        let vram_table = mirrored_down / 0x400;
-                        let address = match (self.nametable_mirroring, vram_table) {
-                            (HORIZONTAL, 1) | (HORIZONTAL, 3) => mirrored_down - 0x400,
-                            (VERTICAL, 1) | (VERTICAL, 3) => mirrored_down - 0x800,
-                            _ => mirrored_down
-                        };
-                        self.increment_vram();
-                        let result = self.ram[address as usize];
-                        warn!("PPU read: {:#01X} from address {:#01X}", result, address);
-                        return result
+                    _ => {
+                        let result = self.internal_buffer;
+                        self.internal_buffer = value;
+                        result
                     }
-                    _ => panic!("Unknown vram address: {:#01X}", self.vram_address)
                 };
-                let result = self.internal_buffer;
-                self.internal_buffer = new_data;
+                self.refresh_latch(result, 0xFF);
                 result
             }, // PPUDATA
+            _ => self.peek(address)
+        }
+    }
+
+    // What fetch would return, leaving vblank, the latch, the read buffer and the VRAM address alone
+    pub fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x2000 => self.open_bus(), // PPUCTRL
+            0x2001 => self.open_bus(), // PPUMASK
+            0x2002 => self.status_register(), // PPUSTATUS
+            0x2003 => self.open_bus(), // OAMADDR
+            0x2004 => self.oam[self.oam_address as usize], // OAMDATA
+            0x2005 => self.open_bus(), // PPUSCROLL
+            0x2006 => self.open_bus(), // PPUADDR
+            0x2007 => match self.get_vram_address() as u16 {
+                address @ 0x3F00..=0x3FFF => self.ram[self.vram_index(address)],
+                _ => self.internal_buffer
+            }, // PPUDATA
             _ => panic!("Ppu port not implemented")
         }
     }
 
+    // The low five bits of PPUSTATUS are not driven and come from the I/O latch
+    fn status_register(&self) -> u8 {
+        (self.open_bus() & 0b00_01_11_11) | (self.ppu_status & 0b11_10_00_00)
+    }
+
+    // Value left on the PPU data bus by the last register access, minus the bits that decayed since
+    fn open_bus(&self) -> u8 {
+        (0..8).filter(|bit| self.total_cycles.saturating_sub(self.latch_refreshed[*bit]) < OPEN_BUS_DECAY_CYCLES)
+            .fold(0, |value, bit| value | (self.latch & (1 << bit)))
+    }
+
+    // Only the bits in the mask are driven, the rest keep decaying
+    fn refresh_latch(&mut self, value: u8, mask: u8) {
+        self.latch = (self.latch & !mask) | (value & mask);
+        for bit in (0..8).filter(|bit| nth_bit(mask, *bit)) {
+            self.latch_refreshed[bit as usize] = self.total_cycles;
+        }
+    }
+
+    // Index into ram backing a PPU address, nametables are folded by the mirroring into two
+    // physical tables at $2000 and $2400, palette entries $3F10/$3F14/$3F18/$3F1C alias the backdrop ones
+    fn vram_index(&self, address: u16) -> usize {
+        match address {
+            0..=0x1FFF => address as usize,
+            0x2000..=0x3EFF => {
+                let offset = address & 0x03FF;
+                let vram_table = (address >> 10) & 0b11;
+                let physical_table = match self.nametable_mirroring {
+                    HORIZONTAL => vram_table >> 1,
+                    VERTICAL => vram_table & 1
+                };
+                (0x2000 + (physical_table * 0x400) + offset) as usize
+            },
+            0x3F00..=0x3FFF => {
+                let entry = address & 0x1F;
+                let entry = if (entry & 0x13) == 0x10 { entry & 0x0F } else { entry };
+                (0x3F00 + entry) as usize
+            },
+            _ => panic!("Unknown vram address: {:#01X}", address)
+        }
+    }
+
     pub fn save(&mut self, address: u16, value: u8) {
         info!("ppu save: {:#01X} at address {:#01X}", value, address);
         match address {
             0x2000 => {
                 self.status = value;
+                self.temp_address = (self.temp_address & !0x0C00) | (((value & 0b11) as u16) << 10);
             }, // PPUCTRL
-            0x2001 => {}, // PPUMASK
+            0x2001 => {
+                self.mask = value;
+            }, // PPUMASK
             0x2002 => {
                 self.latch = value;
             }, // PPUSTATUS
@@ -302,40 +570,67 @@ impl Ppu {
                 self.oam_address = (Wrapping(self.oam_address) + Wrapping(1)).0;
                 self.latch = value;
             }, // OAMDATA
-            0x2005 => {}, // PPUSCROLL
+            0x2005 => {
+                let value = value as u16;
+                if self.write_toggle {
+                    self.temp_address = (self.temp_address & !0x73E0) | ((value & 0b111) << 12) | ((value >> 3) << 5);
+                } else {
+                    self.temp_address = (self.temp_address & !0x001F) | (value >> 3);
+                    self.fine_x = (value & 0b111) as u8;
+                }
+                self.write_toggle = !self.write_toggle;
+            }, // PPUSCROLL
             0x2006 => {
-                let address = combine_u8(value, self.latch);
-                self.vram_address = address & 0x3FFF;
-                self.latch = value;
+                // High byte first with bit 14 cleared, v only picks the address up on the second write
+                let value = value as u16;
+                if self.write_toggle {
+                    self.temp_address = (self.temp_address & 0xFF00) | value;
+                    self.vram_address = self.temp_address;
+                } else {
+                    self.temp_address = (self.temp_address & 0x00FF) | ((value & 0x3F) << 8);
+                }
+                self.write_toggle = !self.write_toggle;
             }, // PPUADDR
             0x2007 => {
-                // TODO: Internal buffer
-                let address = self.get_vram_address();
+                // Pattern tables are CHR ROM
+                let address = self.get_vram_address() as u16;
                 if address >= 0x2000 {
-                    self.ram[address] = value;
-                    self.increment_vram();
+                    let index = self.vram_index(address);
+                    self.ram[index] = value;
                 }
+                self.increment_vram();
             }, // PPUDATA
             _ => panic!("Ppu port not implemented")
         }
-        self.latch = value;
+        self.refresh_latch(value, 0xFF);
     }
 
-    fn get_vram_address(&mut self) -> usize {
+    fn get_vram_address(&self) -> usize {
         (self.vram_address & 0x3FFF) as usize
     }
 
+    // While the PPU renders, a PPUDATA access bumps coarse x and y like the fetches do
     fn increment_vram(&mut self) {
+        if self.rendering_enabled() && ((self.scanline <= 239) || (self.scanline == 261)) {
+            self.increment_coarse_x();
+            self.increment_y();
+            return
+        }
         self.vram_address = (Wrapping(self.vram_address) + Wrapping(self.get_vram_increment() as u16)).0;
-        self.vram_address &= 0x3FFF;
+        self.vram_address &= 0x7FFF;
     }
 
-    fn get_nmi_output(&mut self) -> bool {
+    fn get_nmi_output(&self) -> bool {
         nth_bit(self.status, 7)
     }
 
-    fn get_sprite_size(&mut self) -> u8 {
-        if nth_bit(self.status, 3) {
+    // The /NMI output, low while vblank is flagged and PPUCTRL bit 7 enables it
+    pub fn nmi_line(&self) -> bool {
+        self.get_nmi_output() && self.nmi_occurred
+    }
+
+    fn get_sprite_size(&self) -> u8 {
+        if nth_bit(self.status, 5) {
             16
         } else {
             8
@@ -358,16 +653,6 @@ impl Ppu {
         }
     }
 
-    fn get_base_nametable_address(&self) -> u16 {
-        match self.status & 0b0000_0011 {
-            0 => 0x2000,
-            1 => 0x2400,
-            2 => 0x2800,
-            3 => 0x2C00,
-            _ => panic!("Invalid nametable address {:#01X}", self.status)
-        }
-    }
-
     fn get_vram_increment(&mut self) -> u8 {
         if nth_bit(self.status, 2) {
             32
@@ -376,18 +661,462 @@ impl Ppu {
         }
     }
 
-    pub fn fetch_internal(&mut self, address: u16) -> u8 {
-        let real_address = address & PPU_ADDRESSABLE_RANGE;
-        return 0;
+    pub fn emulate(&mut self) {
+        self.tick();
+        self.tick();
+        self.tick();
+    }
+}
+
+// The frame buffer is output only and is left alone, the next frame redraws it
+impl Snapshot for Ppu {
+    fn snapshot(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.ram);
+        writer.write_bytes(&self.oam);
+        writer.write_u16(self.cycles);
+        writer.write_u16(self.scanline);
+        writer.write_u8(self.ppu_status);
+        writer.write_u16(self.vram_address);
+        writer.write_u16(self.temp_address);
+        writer.write_u8(self.fine_x);
+        writer.write_bool(self.write_toggle);
+        writer.write_u8(self.latch);
+        for refreshed in self.latch_refreshed.iter() {
+            writer.write_u64(*refreshed);
+        }
+        writer.write_u8(self.last_register);
+        writer.write_bool(self.nmi_occurred);
+        writer.write_u8(self.status);
+        writer.write_u8(self.mask);
+        writer.write_u8(self.next_tile);
+        writer.write_u8(self.next_attribute);
+        writer.write_u8(self.next_pattern_low);
+        writer.write_u8(self.next_pattern_high);
+        writer.write_u16(self.pattern_shift_low);
+        writer.write_u16(self.pattern_shift_high);
+        writer.write_u16(self.attribute_shift_low);
+        writer.write_u16(self.attribute_shift_high);
+        writer.write_u8(self.internal_buffer);
+        writer.write_u8(self.oam_address);
+        writer.write_bytes(&self.secondary_oam);
+        writer.write_u8(self.sprite_count as u8);
+        writer.write_bool(self.sprite_zero_next);
+        writer.write_u8(self.line_sprites.len() as u8);
+        for sprite in self.line_sprites.iter() {
+            writer.write_u8(sprite.x);
+            writer.write_u8(sprite.attributes);
+            writer.write_u8(sprite.pattern_low);
+            writer.write_u8(sprite.pattern_high);
+        }
+        writer.write_bool(self.sprite_zero_line);
+        writer.write_u64(self.total_cycles);
+        writer.write_u64(self.frame_count);
+    }
+
+    fn restore(&mut self, reader: &mut StateReader) -> Result<(), String> {
+        reader.read_into(&mut self.ram)?;
+        reader.read_into(&mut self.oam)?;
+        self.cycles = reader.read_u16()?;
+        self.scanline = reader.read_u16()?;
+        self.ppu_status = reader.read_u8()?;
+        self.vram_address = reader.read_u16()?;
+        self.temp_address = reader.read_u16()?;
+        self.fine_x = reader.read_u8()?;
+        self.write_toggle = reader.read_bool()?;
+        self.latch = reader.read_u8()?;
+        for refreshed in self.latch_refreshed.iter_mut() {
+            *refreshed = reader.read_u64()?;
+        }
+        self.last_register = reader.read_u8()?;
+        self.nmi_occurred = reader.read_bool()?;
+        self.status = reader.read_u8()?;
+        self.mask = reader.read_u8()?;
+        self.next_tile = reader.read_u8()?;
+        self.next_attribute = reader.read_u8()?;
+        self.next_pattern_low = reader.read_u8()?;
+        self.next_pattern_high = reader.read_u8()?;
+        self.pattern_shift_low = reader.read_u16()?;
+        self.pattern_shift_high = reader.read_u16()?;
+        self.attribute_shift_low = reader.read_u16()?;
+        self.attribute_shift_high = reader.read_u16()?;
+        self.internal_buffer = reader.read_u8()?;
+        self.oam_address = reader.read_u8()?;
+        reader.read_into(&mut self.secondary_oam)?;
+        self.sprite_count = reader.read_u8()? as usize;
+        self.sprite_zero_next = reader.read_bool()?;
+        self.line_sprites.clear();
+        for _ in 0..reader.read_u8()? {
+            let (x, attributes) = (reader.read_u8()?, reader.read_u8()?);
+            let (pattern_low, pattern_high) = (reader.read_u8()?, reader.read_u8()?);
+            self.line_sprites.push(LineSprite { x, attributes, pattern_low, pattern_high });
+        }
+        self.sprite_zero_line = reader.read_bool()?;
+        self.total_cycles = reader.read_u64()?;
+        self.frame_count = reader.read_u64()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peek_status() {
+        let mut ppu = Ppu::new(vec![], NameTableMirroring::VERTICAL);
+        ppu.set_vblank();
+        assert_eq!(ppu.peek(0x2002), 0b1000_0000);
+        assert_eq!(ppu.peek(0x2002), 0b1000_0000);
+        assert_eq!(ppu.fetch(0x2002), 0b1000_0000);
+        assert_eq!(ppu.peek(0x2002), 0)
+    }
+
+    #[test]
+    fn test_peek_data() {
+        let mut ppu = Ppu::new(vec![0x11, 0x22], NameTableMirroring::VERTICAL);
+        ppu.save(0x2006, 0x00);
+        ppu.save(0x2006, 0x00);
+        assert_eq!(ppu.peek(0x2007), 0);
+        assert_eq!(ppu.fetch(0x2007), 0);
+        assert_eq!(ppu.peek(0x2007), 0x11);
+        assert_eq!(ppu.peek(0x2007), 0x11);
+        assert_eq!(ppu.fetch(0x2007), 0x11);
+        assert_eq!(ppu.fetch(0x2007), 0x22)
+    }
+
+    #[test]
+    fn test_buffered_nametable_read() {
+        let mut ppu = Ppu::new(vec![], NameTableMirroring::VERTICAL);
+        ppu.ram[0x2005] = 0x33;
+        ppu.ram[0x2700] = 0x44;
+        ppu.ram[0x3F00] = 0x0F;
+        ppu.save(0x2006, 0x20);
+        ppu.save(0x2006, 0x05);
+        assert_eq!(ppu.fetch(0x2007), 0);
+        ppu.save(0x2006, 0x20);
+        ppu.save(0x2006, 0x05);
+        assert_eq!(ppu.peek(0x2007), 0x33);
+        assert_eq!(ppu.fetch(0x2007), 0x33);
+        // Palette reads skip the buffer but load it from the nametable below, $2F00 mirrors $2700
+        ppu.save(0x2006, 0x3F);
+        ppu.save(0x2006, 0x00);
+        assert_eq!(ppu.peek(0x2007), 0x0F);
+        assert_eq!(ppu.fetch(0x2007), 0x0F);
+        ppu.save(0x2006, 0x20);
+        ppu.save(0x2006, 0x00);
+        assert_eq!(ppu.fetch(0x2007), 0x44)
+    }
+
+    // Tile 1 is solid colour 1, the top left tile of the first nametable uses it
+    fn create_tile_ppu() -> Ppu {
+        let mut chr_rom = vec![0; 0x2000];
+        chr_rom[16..24].copy_from_slice(&[0xFF; 8]);
+        let mut ppu = Ppu::new(chr_rom, NameTableMirroring::VERTICAL);
+        ppu.ram[0x2000] = 1;
+        ppu.ram[0x3F00] = 0x0F;
+        ppu.ram[0x3F01] = 0x30;
+        ppu.save(0x2001, 0b0001_1110);
+        ppu
+    }
+
+    fn run_frame(ppu: &mut Ppu) {
+        let frame = ppu.frame();
+        while ppu.frame() == frame {
+            ppu.tick();
+        }
+    }
+
+    fn pixel(ppu: &Ppu, x: usize, y: usize) -> Colour {
+        ppu.frame_buffer()[y * FRAME_WIDTH + x]
+    }
+
+    #[test]
+    fn test_background_tile() {
+        let mut ppu = create_tile_ppu();
+        // The first frame starts without the tiles prefetched on the pre-render line
+        run_frame(&mut ppu);
+        run_frame(&mut ppu);
+        let white = ppu.palette_colour(0x3F01);
+        let backdrop = ppu.palette_colour(0x3F00);
+        assert!((0..8).all(|x| pixel(&ppu, x, 0) == white && pixel(&ppu, x, 7) == white));
+        assert_eq!(pixel(&ppu, 8, 0), backdrop);
+        assert_eq!(pixel(&ppu, 0, 8), backdrop);
+        assert_eq!(pixel(&ppu, 255, 239), backdrop)
+    }
+
+    #[test]
+    fn test_mid_frame_palette_change() {
+        let mut ppu = create_tile_ppu();
+        run_frame(&mut ppu);
+        let top = ppu.palette_colour(0x3F00);
+        while ppu.scanline() != 120 {
+            ppu.tick();
+        }
+        ppu.ram[0x3F00] = 0x16;
+        run_frame(&mut ppu);
+        let bottom = ppu.palette_colour(0x3F00);
+        assert_ne!(top, bottom);
+        assert_eq!(pixel(&ppu, 8, 119), top);
+        assert_eq!(pixel(&ppu, 8, 120), bottom);
+        assert_eq!(pixel(&ppu, 8, 239), bottom)
+    }
+
+    // Sprite palette 0 draws colour 1 in red, tile 2 only has its top left pixel set
+    fn create_sprite_ppu() -> Ppu {
+        let mut ppu = create_tile_ppu();
+        ppu.ram[32] = 0x80;
+        ppu.ram[0x3F11] = 0x16;
+        ppu.oam.iter_mut().for_each(|byte| *byte = 0xFF);
+        ppu
+    }
+
+    fn set_sprite(ppu: &mut Ppu, index: usize, sprite: [u8; 4]) {
+        ppu.oam[index * 4..index * 4 + 4].copy_from_slice(&sprite);
+    }
+
+    #[test]
+    fn test_sprite_position_and_flip() {
+        let mut ppu = create_sprite_ppu();
+        let red = ppu.palette_colour(0x3F11);
+        let backdrop = ppu.palette_colour(0x3F00);
+        // Sprites are drawn one line below their OAM y
+        set_sprite(&mut ppu, 0, [9, 1, 0, 20]);
+        set_sprite(&mut ppu, 1, [100, 2, 0, 50]);
+        set_sprite(&mut ppu, 2, [100, 2, 0b1100_0000, 70]);
+        run_frame(&mut ppu);
+        run_frame(&mut ppu);
+        assert!((20..28).all(|x| pixel(&ppu, x, 10) == red && pixel(&ppu, x, 17) == red));
+        assert_eq!(pixel(&ppu, 19, 10), backdrop);
+        assert_eq!(pixel(&ppu, 28, 10), backdrop);
+        assert_eq!(pixel(&ppu, 20, 9), backdrop);
+        assert_eq!(pixel(&ppu, 20, 18), backdrop);
+        assert_eq!(pixel(&ppu, 50, 101), red);
+        assert_eq!(pixel(&ppu, 57, 108), backdrop);
+        assert_eq!(pixel(&ppu, 70, 101), backdrop);
+        assert_eq!(pixel(&ppu, 77, 108), red)
+    }
+
+    #[test]
+    fn test_sprite_priority_and_sprite_zero_hit() {
+        let mut ppu = create_sprite_ppu();
+        let red = ppu.palette_colour(0x3F11);
+        let white = ppu.palette_colour(0x3F01);
+        // Behind the background, only visible where the background tile is transparent
+        set_sprite(&mut ppu, 0, [0, 1, 0b0010_0000, 4]);
+        set_sprite(&mut ppu, 1, [0, 1, 0, 2]);
+        run_frame(&mut ppu);
+        assert_eq!(ppu.peek(0x2002) & 0b0100_0000, 0b0100_0000);
+        while (ppu.scanline(), ppu.dot()) != (1, 4) {
+            ppu.tick();
+        }
+        // Cleared on the pre-render line, set again by the first overlapping pixel
+        assert_eq!(ppu.peek(0x2002) & 0b0100_0000, 0);
+        ppu.tick();
+        assert_eq!(ppu.peek(0x2002) & 0b0100_0000, 0b0100_0000);
+        run_frame(&mut ppu);
+        // Sprite 1 wins over sprite 0 only where sprite 0 is transparent
+        assert_eq!(pixel(&ppu, 2, 1), red);
+        assert_eq!(pixel(&ppu, 4, 1), white);
+        assert_eq!(pixel(&ppu, 8, 1), red);
+        assert_eq!(pixel(&ppu, 11, 1), red)
+    }
+
+    #[test]
+    fn test_tall_sprites() {
+        let mut ppu = create_sprite_ppu();
+        let red = ppu.palette_colour(0x3F11);
+        // Tiles 2 and 3 of the second pattern table, a dot in the top left and one in the bottom right
+        ppu.ram[0x1020] = 0x80;
+        ppu.ram[0x1037] = 0x01;
+        ppu.save(0x2000, 0b0010_0000);
+        set_sprite(&mut ppu, 0, [49, 3, 0, 30]);
+        set_sprite(&mut ppu, 1, [49, 3, 0b1000_0000, 60]);
+        run_frame(&mut ppu);
+        run_frame(&mut ppu);
+        assert_eq!(pixel(&ppu, 30, 50), red);
+        assert_eq!(pixel(&ppu, 37, 65), red);
+        assert_eq!(pixel(&ppu, 37, 50), ppu.palette_colour(0x3F00));
+        assert_eq!(pixel(&ppu, 67, 50), red);
+        assert_eq!(pixel(&ppu, 60, 65), red);
+        assert_eq!(pixel(&ppu, 60, 50), ppu.palette_colour(0x3F00))
+    }
+
+    #[test]
+    fn test_sprite_size_change_before_fetch() {
+        let mut ppu = create_sprite_ppu();
+        set_sprite(&mut ppu, 0, [10, 2, 0b1000_0000, 40]);
+        ppu.save(0x2000, 0b0010_0000);
+        ppu.scanline = 25;
+        ppu.evaluate_sprites();
+        ppu.save(0x2000, 0);
+        ppu.fetch_sprites();
+        // Row 15 of the tall sprite is row 7 of the small one, flipped onto the dot in row 0
+        assert_eq!(ppu.sprite_pixel(40).map(|(address, _, _)| address), Some(0x3F11));
+        assert_eq!(ppu.sprite_pixel(41), None)
+    }
+
+    #[test]
+    fn test_sprite_limit_and_overflow() {
+        let mut ppu = create_sprite_ppu();
+        for index in 0..9 {
+            set_sprite(&mut ppu, index, [10, 1, 0, index as u8 * 8]);
+        }
+        ppu.scanline = 12;
+        ppu.evaluate_sprites();
+        assert_eq!(ppu.sprite_count, 8);
+        assert!(ppu.sprite_zero_next);
+        assert_eq!(ppu.peek(0x2002) & 0b0010_0000, 0b0010_0000);
+        ppu.fetch_sprites();
+        assert_eq!(ppu.sprite_pixel(63).map(|(address, _, _)| address), Some(0x3F11));
+        assert_eq!(ppu.sprite_pixel(64), None);
+        // With exactly eight sprites the ninth y is checked, then the tile byte of the tenth
+        ppu.ppu_status = 0;
+        set_sprite(&mut ppu, 8, [0xFF, 0xFF, 0xFF, 0xFF]);
+        ppu.evaluate_sprites();
+        assert_eq!(ppu.peek(0x2002) & 0b0010_0000, 0);
+        set_sprite(&mut ppu, 9, [0xFF, 12, 0xFF, 0xFF]);
+        ppu.evaluate_sprites();
+        assert_eq!(ppu.peek(0x2002) & 0b0010_0000, 0b0010_0000)
+    }
+
+    #[test]
+    fn test_rendering_disabled() {
+        let mut ppu = create_tile_ppu();
+        ppu.ram[0x3F05] = 0x16;
+        ppu.save(0x2001, 0);
+        ppu.save(0x2006, 0x21);
+        ppu.save(0x2006, 0x00);
+        run_frame(&mut ppu);
+        // v is left alone and the backdrop covers the tile
+        assert_eq!(ppu.vram_address, 0x2100);
+        assert_eq!(pixel(&ppu, 0, 0), ppu.palette_colour(0x3F00));
+        ppu.save(0x2006, 0x3F);
+        ppu.save(0x2006, 0x05);
+        run_frame(&mut ppu);
+        assert_eq!(pixel(&ppu, 0, 0), ppu.palette_colour(0x3F05));
+        assert_eq!(pixel(&ppu, 255, 239), ppu.palette_colour(0x3F05))
+    }
+
+    #[test]
+    fn test_data_access_while_rendering() {
+        let mut ppu = create_tile_ppu();
+        ppu.scanline = 100;
+        ppu.vram_address = 0x0000;
+        ppu.fetch(0x2007);
+        assert_eq!(ppu.vram_address, 0x1001);
+        ppu.scanline = 241;
+        ppu.fetch(0x2007);
+        assert_eq!(ppu.vram_address, 0x1002)
+    }
+
+    #[test]
+    fn test_left_column_clipping() {
+        let mut ppu = create_sprite_ppu();
+        let red = ppu.palette_colour(0x3F11);
+        let backdrop = ppu.palette_colour(0x3F00);
+        ppu.save(0x2001, 0b0001_1000);
+        set_sprite(&mut ppu, 0, [20, 1, 0, 4]);
+        run_frame(&mut ppu);
+        run_frame(&mut ppu);
+        assert!((0..8).all(|x| pixel(&ppu, x, 0) == backdrop && pixel(&ppu, x, 21) == backdrop));
+        assert!((8..12).all(|x| pixel(&ppu, x, 21) == red));
+        ppu.save(0x2001, 0b0000_1010);
+        run_frame(&mut ppu);
+        assert_eq!(pixel(&ppu, 0, 0), ppu.palette_colour(0x3F01));
+        assert_eq!(pixel(&ppu, 8, 21), backdrop)
     }
 
-    pub fn save_internal(&mut self, address: u16, value: u8) {
-        let real_address = address & PPU_ADDRESSABLE_RANGE;
+    #[test]
+    fn test_greyscale_and_emphasis() {
+        let mut ppu = Ppu::new(vec![], NameTableMirroring::VERTICAL);
+        ppu.ram[0x3F00] = 0x16;
+        ppu.save(0x2001, 0b0000_0001);
+        let (r, g, b) = PALETTE[0x10];
+        assert_eq!(ppu.palette_colour(0x3F00), Colour{r, g, b});
+        ppu.save(0x2001, 0b0010_0000);
+        let (r, g, b) = PALETTE[0x16];
+        assert_eq!(ppu.palette_colour(0x3F00), Colour{r, g: (g as u16 * 13 / 16) as u8, b: (b as u16 * 13 / 16) as u8})
     }
 
-    pub fn emulate(&mut self, screen: &mut Screen) {
-        self.tick(screen);
-        self.tick(screen);
-        self.tick(screen);
+    #[test]
+    fn test_scroll_registers() {
+        let mut ppu = Ppu::new(vec![], NameTableMirroring::VERTICAL);
+        ppu.save(0x2000, 0b10);
+        ppu.fetch(0x2002);
+        ppu.save(0x2005, 0x7D);
+        assert_eq!((ppu.temp_address, ppu.fine_x, ppu.write_toggle), (0x080F, 0b101, true));
+        ppu.save(0x2005, 0x5E);
+        assert_eq!((ppu.temp_address, ppu.write_toggle), (0x696F, false));
+        ppu.save(0x2006, 0x3D);
+        assert_eq!((ppu.temp_address, ppu.vram_address), (0x3D6F, 0));
+        ppu.save(0x2006, 0xF0);
+        assert_eq!((ppu.temp_address, ppu.vram_address, ppu.write_toggle), (0x3DF0, 0x3DF0, false));
+        // Reading PPUSTATUS resets the toggle between the two writes
+        ppu.save(0x2006, 0x21);
+        ppu.fetch(0x2002);
+        ppu.save(0x2006, 0x22);
+        ppu.save(0x2006, 0x08);
+        assert_eq!(ppu.vram_address, 0x2208)
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_nametable_mirroring() {
+        let horizontal = Ppu::new(vec![], NameTableMirroring::HORIZONTAL);
+        assert_eq!(horizontal.vram_index(0x2400), 0x2000);
+        assert_eq!(horizontal.vram_index(0x2C05), 0x2405);
+        let vertical = Ppu::new(vec![], NameTableMirroring::VERTICAL);
+        assert_eq!(vertical.vram_index(0x2805), 0x2005);
+        assert_eq!(vertical.vram_index(0x3C05), 0x2405);
+        assert_eq!(vertical.vram_index(0x3F14), 0x3F04);
+        assert_eq!(vertical.vram_index(0x3F35), 0x3F15)
+    }
+
+    #[test]
+    fn test_horizontal_scroll() {
+        let mut ppu = create_tile_ppu();
+        ppu.ram[0x2400] = 1;
+        let white = ppu.palette_colour(0x3F01);
+        let backdrop = ppu.palette_colour(0x3F00);
+        // The last four columns of the first nametable, then the second one
+        ppu.save(0x2005, 252);
+        ppu.save(0x2005, 0);
+        run_frame(&mut ppu);
+        run_frame(&mut ppu);
+        assert!((0..4).all(|x| pixel(&ppu, x, 0) == backdrop));
+        assert!((4..12).all(|x| pixel(&ppu, x, 0) == white));
+        assert_eq!(pixel(&ppu, 12, 0), backdrop);
+        assert_eq!(pixel(&ppu, 4, 8), backdrop)
+    }
+
+    #[test]
+    fn test_vertical_scroll() {
+        let mut ppu = create_tile_ppu();
+        let white = ppu.palette_colour(0x3F01);
+        let backdrop = ppu.palette_colour(0x3F00);
+        ppu.save(0x2005, 0);
+        ppu.save(0x2005, 4);
+        run_frame(&mut ppu);
+        run_frame(&mut ppu);
+        assert!((0..4).all(|y| pixel(&ppu, 0, y) == white));
+        assert_eq!(pixel(&ppu, 0, 4), backdrop);
+        // Row 29 is followed by row 0 of the nametable below, which mirrors the first one
+        assert_eq!(pixel(&ppu, 0, 235), backdrop);
+        assert_eq!(pixel(&ppu, 0, 236), white)
+    }
+
+    #[test]
+    fn test_open_bus_decay() {
+        let mut ppu = Ppu::new(vec![], NameTableMirroring::VERTICAL);
+        ppu.save(0x2000, 0xFF);
+        assert_eq!(ppu.fetch(0x2005), 0xFF);
+        ppu.total_cycles += OPEN_BUS_DECAY_CYCLES / 2;
+        // Reading PPUSTATUS only refreshes the top three bits
+        assert_eq!(ppu.fetch(0x2002), 0x1F);
+        ppu.total_cycles += OPEN_BUS_DECAY_CYCLES / 2;
+        assert_eq!(ppu.fetch(0x2000), 0x00);
+        ppu.save(0x2003, 0x00);
+        ppu.oam[0] = 0xA5;
+        assert_eq!(ppu.fetch(0x2004), 0xA5);
+        assert_eq!(ppu.fetch(0x2001), 0xA5)
+    }
+}
@@ -0,0 +1,147 @@
+// Runner for the per-opcode single-step JSON suites (SingleStepTests/ProcessorTests nes6502 format)
+use std::fs;
+use std::path::Path;
+use serde_json::Value;
+use crate::bus::CpuBus;
+use crate::flat_bus::{BusAccess, FlatBus};
+use crate::cpu::Cpu;
+use crate::flags::Flags;
+use crate::trace::CpuState;
+
+// JAM halts forever and the unstable stores and magic-constant opcodes vary between chips
+static SKIPPED_OP_CODES: &[u8] = &[
+    0x02, 0x12, 0x22, 0x32, 0x42, 0x52, 0x62, 0x72, 0x92, 0xB2, 0xD2, 0xF2,
+    0x8B, 0x93, 0x9B, 0x9C, 0x9E, 0x9F, 0xAB
+];
+
+// B and bit 5 only exist on the stack, they are ignored when comparing P
+static STATUS_MASK: u8 = 0b1100_1111;
+
+fn field(value: &Value, name: &str) -> u64 {
+    value[name].as_u64().unwrap_or_else(|| panic!("Missing field {} in {}", name, value))
+}
+
+fn load_state(value: &Value) -> CpuState {
+    CpuState {
+        program_counter: field(value, "pc") as u16,
+        acc: field(value, "a") as u8,
+        reg_x: field(value, "x") as u8,
+        reg_y: field(value, "y") as u8,
+        status: Flags::from(field(value, "p") as u8),
+        stack_pointer: field(value, "s") as u8,
+        cycles: 0
+    }
+}
+
+fn load_ram(value: &Value) -> Vec<(u16, u8)> {
+    value["ram"].as_array().unwrap().iter()
+        .map(|entry| (entry[0].as_u64().unwrap() as u16, entry[1].as_u64().unwrap() as u8))
+        .collect()
+}
+
+fn load_cycles(value: &Value) -> Vec<(u16, u8, BusAccess)> {
+    value.as_array().unwrap().iter()
+        .map(|cycle| {
+            let access = match cycle[2].as_str() {
+                Some("read") => BusAccess::Read,
+                Some("write") => BusAccess::Write,
+                other => panic!("Unknown bus access {:?}", other)
+            };
+            (cycle[0].as_u64().unwrap() as u16, cycle[1].as_u64().unwrap() as u8, access)
+        })
+        .collect()
+}
+
+// Runs a single case, returning a description of the first mismatch
+pub fn run_case(case: &Value) -> Result<(), String> {
+    let name = case["name"].as_str().unwrap_or("unnamed");
+    let mut memory = vec![0; 0x10000];
+    for (address, value) in load_ram(&case["initial"]) {
+        memory[address as usize] = value;
+    }
+    let mut bus = FlatBus::new(memory);
+    bus.record_cycles();
+    let mut cpu = Cpu::new(bus, Some(0));
+    cpu.load_state(&load_state(&case["initial"]));
+    cpu.emulate();
+
+    let expected = load_state(&case["final"]);
+    let actual = cpu.state();
+    let registers = |state: &CpuState| (
+        state.program_counter, state.acc, state.reg_x, state.reg_y,
+        state.status.bits() & STATUS_MASK, state.stack_pointer
+    );
+    if registers(&actual) != registers(&expected) {
+        return Err(format!("{}: registers (pc, a, x, y, p, s)\nexpected: {:X?}\nactual:   {:X?}",
+                           name, registers(&expected), registers(&actual)))
+    }
+    let cycles = cpu.bus_mut().take_cycle_log();
+    for (address, value) in load_ram(&case["final"]) {
+        let actual = cpu.bus().peek(address);
+        if actual != value {
+            return Err(format!("{}: memory at {:#06X}, expected {:#04X}, actual {:#04X}", name, address, value, actual))
+        }
+    }
+    let expected_cycles = load_cycles(&case["cycles"]);
+    if cycles != expected_cycles {
+        return Err(format!("{}: cycles\nexpected: {:X?}\nactual:   {:X?}", name, expected_cycles, cycles))
+    }
+    Ok(())
+}
+
+// Runs every case in a suite file, returning all failures
+pub fn run_file(path: &Path) -> Vec<String> {
+    let suite: Value = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+    suite.as_array().unwrap().iter()
+        .filter_map(|case| run_case(case).err())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_case() {
+        let case: Value = serde_json::from_str(r#"{
+            "name": "b5 10 00",
+            "initial": {"pc": 512, "s": 253, "a": 0, "x": 2, "y": 0, "p": 36,
+                        "ram": [[512, 181], [513, 16], [18, 128]]},
+            "final": {"pc": 514, "s": 253, "a": 128, "x": 2, "y": 0, "p": 164,
+                      "ram": [[512, 181], [513, 16], [18, 128]]},
+            "cycles": [[512, 181, "read"], [513, 16, "read"], [16, 0, "read"], [18, 128, "read"]]
+        }"#).unwrap();
+        assert_eq!(run_case(&case), Ok(()))
+    }
+
+    #[test]
+    fn test_reports_mismatch() {
+        let case: Value = serde_json::from_str(r#"{
+            "name": "e8",
+            "initial": {"pc": 512, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36, "ram": [[512, 232]]},
+            "final": {"pc": 513, "s": 253, "a": 0, "x": 2, "y": 0, "p": 36, "ram": [[512, 232]]},
+            "cycles": [[512, 232, "read"], [513, 0, "read"]]
+        }"#).unwrap();
+        assert!(run_case(&case).unwrap_err().starts_with("e8: registers"))
+    }
+
+    // Suite files go in testing/ProcessorTests/nes6502/v1, named after the op code (a9.json)
+    #[test]
+    #[ignore = "needs testing/ProcessorTests/nes6502/v1, run with cargo test -- --ignored"]
+    fn test_processor_tests() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("testing/ProcessorTests/nes6502/v1");
+        assert!(root.exists(), "{} is required", root.display());
+        let mut failures = vec![];
+        for op_code in 0..=0xFFu8 {
+            let path = root.join(format!("{:02x}.json", op_code));
+            if SKIPPED_OP_CODES.contains(&op_code) || !path.exists() {
+                continue
+            }
+            let file_failures = run_file(&path);
+            if let Some(first) = file_failures.first() {
+                failures.push(format!("{:#04X}: {} failing, first: {}", op_code, file_failures.len(), first));
+            }
+        }
+        assert!(failures.is_empty(), "{}", failures.join("\n"))
+    }
+}
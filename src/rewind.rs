@@ -0,0 +1,154 @@
+use std::collections::VecDeque;
+
+static FRAMES_PER_SECOND: u64 = 60;
+
+// Save states of the last few seconds, newest kept whole and every older one as its difference to the next
+#[derive(Debug)]
+pub struct Rewind {
+    interval: u64,
+    capacity: usize,
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>
+}
+
+impl Rewind {
+    // A state every interval frames, enough of them to cover the seconds of history
+    pub fn new(seconds: u64, interval: u64) -> Rewind {
+        assert!(interval > 0, "Rewind interval has to be at least one frame");
+        Rewind {
+            interval,
+            capacity: (seconds * FRAMES_PER_SECOND / interval) as usize,
+            latest: None,
+            deltas: VecDeque::new()
+        }
+    }
+
+    pub fn is_due(&self, frame: u64) -> bool {
+        self.capacity > 0 && frame.is_multiple_of(self.interval)
+    }
+
+    pub fn push(&mut self, state: Vec<u8>) {
+        if self.capacity == 0 {
+            return
+        }
+        if let Some(previous) = self.latest.take() {
+            self.deltas.push_back(encode_delta(&state, &previous));
+            if self.deltas.len() >= self.capacity {
+                self.deltas.pop_front();
+            }
+        }
+        self.latest = Some(state);
+    }
+
+    // Newest state first, None once the history is used up
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let latest = self.latest.take()?;
+        self.latest = self.deltas.pop_back().map(|delta| decode_delta(&latest, &delta));
+        Some(latest)
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.deltas.len() + self.latest.is_some() as usize
+    }
+}
+
+// Target XORed with the base, stored as runs of (unchanged bytes, changed bytes, changed bytes XORed)
+// Consecutive states of a machine differ in few bytes, so most of it collapses into the run lengths
+fn encode_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut delta = vec![];
+    write_length(&mut delta, target.len());
+    let difference = |index: usize| target[index] ^ base.get(index).copied().unwrap_or(0);
+    let mut index = 0;
+    while index < target.len() {
+        let unchanged = (index..target.len()).take_while(|index| difference(*index) == 0).count();
+        index += unchanged;
+        let changed = (index..target.len()).take_while(|index| difference(*index) != 0).count();
+        write_length(&mut delta, unchanged);
+        write_length(&mut delta, changed);
+        delta.extend((index..index + changed).map(difference));
+        index += changed;
+    }
+    delta
+}
+
+fn decode_delta(base: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut position = 0;
+    let length = read_length(delta, &mut position);
+    let mut target: Vec<u8> = (0..length).map(|index| base.get(index).copied().unwrap_or(0)).collect();
+    let mut index = 0;
+    while position < delta.len() {
+        index += read_length(delta, &mut position);
+        let changed = read_length(delta, &mut position);
+        for byte in delta[position..position + changed].iter() {
+            target[index] ^= *byte;
+            index += 1;
+        }
+        position += changed;
+    }
+    target
+}
+
+// LEB128, seven bits at a time with the top bit marking a continuation
+fn write_length(buffer: &mut Vec<u8>, mut length: usize) {
+    while length >= 0x80 {
+        buffer.push((length as u8 & 0x7F) | 0x80);
+        length >>= 7;
+    }
+    buffer.push(length as u8);
+}
+
+fn read_length(buffer: &[u8], position: &mut usize) -> usize {
+    let mut length = 0;
+    let mut shift = 0;
+    loop {
+        let byte = buffer[*position];
+        *position += 1;
+        length |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return length
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delta_round_trip() {
+        let base: Vec<u8> = (0..1000).map(|index| index as u8).collect();
+        let mut target = base.clone();
+        target[3] = 0xFF;
+        target[500..700].iter_mut().for_each(|byte| *byte = 0);
+        target.push(0x42);
+        let delta = encode_delta(&base, &target);
+        assert!(delta.len() < 220);
+        assert_eq!(decode_delta(&base, &delta), target);
+        assert_eq!(decode_delta(&target, &encode_delta(&target, &base)), base)
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        // One second of history with a state every 20 frames keeps three states
+        let mut rewind = Rewind::new(1, 20);
+        assert!(rewind.is_due(40) && !rewind.is_due(41));
+        for value in 0..5u8 {
+            rewind.push(vec![value; 64]);
+        }
+        assert_eq!(rewind.len(), 3);
+        assert_eq!(rewind.pop(), Some(vec![4; 64]));
+        assert_eq!(rewind.pop(), Some(vec![3; 64]));
+        assert_eq!(rewind.pop(), Some(vec![2; 64]));
+        assert_eq!(rewind.pop(), None)
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut rewind = Rewind::new(0, 1);
+        rewind.push(vec![1]);
+        assert!(!rewind.is_due(0));
+        assert_eq!(rewind.pop(), None)
+    }
+}
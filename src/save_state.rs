@@ -0,0 +1,176 @@
+use std::path::{Path, PathBuf};
+
+static MAGIC: &[u8] = b"RNES";
+// Bumped whenever a component changes what it writes, older states are rejected
+static VERSION: u8 = 6;
+
+// Components write their fields in a fixed order and read them back in the same order
+pub trait Snapshot {
+    fn snapshot(&self, writer: &mut StateWriter);
+
+    fn restore(&mut self, reader: &mut StateReader) -> Result<(), String>;
+}
+
+// Little endian, no padding, variable length fields are prefixed with their length
+#[derive(Debug, Default)]
+pub struct StateWriter {
+    buffer: Vec<u8>
+}
+
+impl StateWriter {
+    pub fn new() -> StateWriter {
+        let mut writer = StateWriter { buffer: vec![] };
+        writer.buffer.extend_from_slice(MAGIC);
+        writer.write_u8(VERSION);
+        writer
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.buffer.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+#[derive(Debug)]
+pub struct StateReader<'a> {
+    buffer: &'a [u8],
+    position: usize
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(buffer: &'a [u8]) -> Result<StateReader<'a>, String> {
+        let mut reader = StateReader { buffer, position: 0 };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(String::from("not a save state"))
+        }
+        let version = reader.read_u8()?;
+        if version != VERSION {
+            return Err(format!("save state version {} is not supported, expected {}", version, VERSION))
+        }
+        Ok(reader)
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let end = self.position + length;
+        if end > self.buffer.len() {
+            return Err(format!("save state truncated at byte {}", self.buffer.len()))
+        }
+        let bytes = &self.buffer[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, String> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, String> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, String> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub fn read_bytes(&mut self) -> Result<Vec<u8>, String> {
+        let length = self.read_u32()? as usize;
+        Ok(self.take(length)?.to_vec())
+    }
+
+    // Fills a buffer whose size is fixed by the hardware, a different length means the state does not fit
+    pub fn read_into(&mut self, target: &mut [u8]) -> Result<(), String> {
+        let bytes = self.read_bytes()?;
+        if bytes.len() != target.len() {
+            return Err(format!("expected {} bytes of memory, save state has {}", target.len(), bytes.len()))
+        }
+        target.copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<(), String> {
+        if self.position != self.buffer.len() {
+            return Err(format!("{} unexpected bytes at the end of the save state", self.buffer.len() - self.position))
+        }
+        Ok(())
+    }
+}
+
+// FNV-1a, used to tell apart states made with a different ROM
+pub fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811C_9DC5, |hash, byte| (hash ^ *byte as u32).wrapping_mul(0x0100_0193))
+}
+
+// Slots live next to the ROM, game.nes keeps slot 3 in game.ss3
+pub fn slot_path(cartridge_path: &Path, slot: u8) -> PathBuf {
+    cartridge_path.with_extension(format!("ss{}", slot))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut writer = StateWriter::new();
+        writer.write_u8(0x12);
+        writer.write_bool(true);
+        writer.write_u16(0x3456);
+        writer.write_u64(u64::MAX);
+        writer.write_bytes(&[1, 2, 3]);
+        let state = writer.finish();
+        let mut reader = StateReader::new(&state).unwrap();
+        assert_eq!(reader.read_u8(), Ok(0x12));
+        assert_eq!(reader.read_bool(), Ok(true));
+        assert_eq!(reader.read_u16(), Ok(0x3456));
+        assert_eq!(reader.read_u64(), Ok(u64::MAX));
+        let mut memory = [0; 3];
+        assert_eq!(reader.read_into(&mut memory), Ok(()));
+        assert_eq!(memory, [1, 2, 3]);
+        assert_eq!(reader.finish(), Ok(()))
+    }
+
+    #[test]
+    fn test_rejects_bad_states() {
+        assert_eq!(StateReader::new(b"NES\x1A\x01").unwrap_err(), "not a save state");
+        assert_eq!(StateReader::new(b"RNES\x00").unwrap_err(), "save state version 0 is not supported, expected 6");
+        let mut reader = StateReader::new(b"RNES\x06\x05").unwrap();
+        assert_eq!(reader.read_u16().unwrap_err(), "save state truncated at byte 6")
+    }
+}
@@ -2,13 +2,12 @@ extern crate winit;
 extern crate pixels;
 
 
-use log::{info, warn};
+use log::warn;
 use core::fmt;
 use self::winit::event_loop::EventLoop;
 use self::winit::dpi::{PhysicalSize, LogicalSize, LogicalPosition};
 use self::pixels::{SurfaceTexture, Pixels};
 use self::winit::window::Window;
-use winit_input_helper::WinitInputHelper;
 use crate::ppu::Colour;
 
 const SCREEN_WIDTH: u32 = 256;
@@ -21,42 +20,34 @@ pub struct Screen {
 
 impl Screen {
     pub fn new(event_loop: &EventLoop<()>) -> Screen {
-        let mut input = WinitInputHelper::new();
         let (window, p_width, p_height, mut _hidpi_factor) =
-            Screen::create_window("NES", &event_loop);
+            Screen::create_window("NES", event_loop);
 
         let surface_texture = SurfaceTexture::new(p_width, p_height, &window);
         let mut pixels = Pixels::new(SCREEN_WIDTH, SCREEN_HEIGHT, surface_texture)
             .unwrap();
-        pixels.render();
+        if let Err(error) = pixels.render() {
+            warn!("Could not render: {}", error);
+        }
         Screen {
             pixels,
             window,
         }
     }
 
-    pub fn draw_pixels(&mut self, frame: &Vec<(u16, u16, Colour)>) {
+    // One colour per pixel, row by row
+    pub fn draw_frame(&mut self, frame: &[Colour]) {
         let screen = self.pixels.get_frame();
-        for pixel in frame.iter() {
-            let (x, y, colour) = pixel;
-            let address = (((*y as u32 * SCREEN_WIDTH) + *x as u32) * 4) as usize;
-            screen[address] = colour.r;
-            screen[address + 1] = colour.g;
-            screen[address + 2] = colour.b;
-            screen[address + 3] = 0;
+        for (pixel, colour) in screen.chunks_exact_mut(4).zip(frame.iter()) {
+            pixel[0] = colour.r;
+            pixel[1] = colour.g;
+            pixel[2] = colour.b;
+            pixel[3] = 0xff;
         }
-        self.pixels.render();
-        self.window.request_redraw();
-    }
-
-    pub fn clear(&mut self) {
-        let frame = self.pixels.get_frame();
-        for pixel in frame.chunks_exact_mut(4) {
-            pixel[0] = 0x00; // R
-            pixel[1] = 0x00; // G
-            pixel[2] = 0x00; // B
-            pixel[3] = 0xff; // A
+        if let Err(error) = self.pixels.render() {
+            warn!("Could not render frame: {}", error);
         }
+        self.window.request_redraw();
     }
 
     fn create_window(
@@ -67,7 +58,7 @@ impl Screen {
         let window = winit::window::WindowBuilder::new()
             .with_visible(false)
             .with_title(title)
-            .build(&event_loop)
+            .build(event_loop)
             .unwrap();
         let hidpi_factor = window.scale_factor();
 
@@ -0,0 +1,185 @@
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use log::{info, warn};
+use crate::bus::CpuBus;
+use crate::cartridge::CartridgeLoader;
+use crate::console::Console;
+use crate::cpu::Cpu;
+use crate::util::read_file;
+
+// Blargg's test ROMs report through PRG-RAM: status at $6000, signature at $6001, text from $6004
+static STATUS_ADDRESS: u16 = 0x6000;
+static SIGNATURE_ADDRESS: u16 = 0x6001;
+static SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+static MESSAGE_ADDRESS: u16 = 0x6004;
+static MESSAGE_UPPER_BOUNDARY: u16 = 0x7FFF;
+
+static STATUS_RUNNING: u8 = 0x80;
+static STATUS_NEEDS_RESET: u8 = 0x81;
+
+// The reset button has to be pressed at least 100ms after the ROM asks for it
+static RESET_DELAY_CYCLES: u64 = 200_000;
+// A minute of emulated time
+static TIMEOUT_CYCLES: u64 = 60 * 1_789_773;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TestRomStatus {
+    Passed,
+    Failed(u8),
+    Timeout,
+    Crashed
+}
+
+#[derive(Clone, Debug)]
+pub struct TestRomResult {
+    pub status: TestRomStatus,
+    pub message: String
+}
+
+impl TestRomResult {
+    pub fn passed(&self) -> bool {
+        self.status == TestRomStatus::Passed
+    }
+}
+
+// Runs a ROM headlessly until it reports a result through $6000
+pub fn run_test_rom(path: &Path) -> TestRomResult {
+    info!("Running test rom {}", path.display());
+    run_payload(read_file(path))
+}
+
+fn run_payload(payload: Vec<u8>) -> TestRomResult {
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut cpu = Console::boot(CartridgeLoader::load_cartridge(payload));
+        run_until_finished(&mut cpu)
+    }));
+    outcome.unwrap_or_else(|error| {
+        let message = error.downcast_ref::<String>().cloned()
+            .or_else(|| error.downcast_ref::<&str>().map(|message| message.to_string()))
+            .unwrap_or_default();
+        TestRomResult { status: TestRomStatus::Crashed, message }
+    })
+}
+
+// Runs every .nes file below the directory, sorted by path
+pub fn run_test_rom_directory(directory: &Path) -> Vec<(PathBuf, TestRomResult)> {
+    let mut roms = vec![];
+    collect_roms(directory, &mut roms);
+    roms.sort();
+    roms.into_iter()
+        .map(|rom| {
+            let result = run_test_rom(&rom);
+            (rom, result)
+        })
+        .collect()
+}
+
+fn collect_roms(directory: &Path, roms: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(directory).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            collect_roms(&path, roms);
+        } else if path.extension().is_some_and(|extension| extension == "nes") {
+            roms.push(path);
+        }
+    }
+}
+
+fn run_until_finished(cpu: &mut Cpu) -> TestRomResult {
+    let mut started = false;
+    let mut reset_at = None;
+    while cpu.total_cycles() < TIMEOUT_CYCLES {
+        cpu.emulate();
+        if !has_signature(cpu) {
+            continue
+        }
+        let status = cpu.bus().peek(STATUS_ADDRESS);
+        if status == STATUS_RUNNING {
+            started = true;
+        } else if status == STATUS_NEEDS_RESET {
+            match reset_at {
+                None => reset_at = Some(cpu.total_cycles() + RESET_DELAY_CYCLES),
+                Some(cycles) if cpu.total_cycles() >= cycles => {
+                    info!("Test rom requested a reset");
+                    cpu.reset();
+                    reset_at = None;
+                },
+                _ => {}
+            }
+        } else if started {
+            let status = if status == 0 { TestRomStatus::Passed } else { TestRomStatus::Failed(status) };
+            return TestRomResult { status, message: read_message(cpu) }
+        }
+    }
+    warn!("Test rom did not finish in {} cycles", TIMEOUT_CYCLES);
+    TestRomResult { status: TestRomStatus::Timeout, message: read_message(cpu) }
+}
+
+fn has_signature(cpu: &Cpu) -> bool {
+    let bus = cpu.bus();
+    (0..SIGNATURE.len() as u16).all(|offset| bus.peek(SIGNATURE_ADDRESS + offset) == SIGNATURE[offset as usize])
+}
+
+fn read_message(cpu: &Cpu) -> String {
+    let bus = cpu.bus();
+    let mut message = vec![];
+    for address in MESSAGE_ADDRESS..=MESSAGE_UPPER_BOUNDARY {
+        let character = bus.peek(address);
+        if character == 0 {
+            break
+        }
+        message.push(character);
+    }
+    String::from_utf8_lossy(&message).trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    fn create_test_rom(program: &[u8]) -> Vec<u8> {
+        let mut payload = vec![0x4E, 0x45, 0x53, 0x1A, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let mut prg_rom = vec![0; 0x4000];
+        prg_rom[..program.len()].copy_from_slice(program);
+        // Reset vector pointing at $8000
+        prg_rom[0x3FFD] = 0x80;
+        payload.append(&mut prg_rom);
+        payload
+    }
+
+    #[test]
+    fn test_status_protocol() {
+        let program = assemble("
+                    LDA #$80
+                    STA $6000
+                    LDX #0
+            copy:   LDA signature,X
+                    STA $6001,X
+                    INX
+                    CPX #6
+                    BNE copy
+                    LDA #$03
+                    STA $6000
+            end:    JMP end
+            signature:
+                    .byte $DE, $B0, $61, \"ok\", 0", 0x8000).unwrap();
+        let result = run_payload(create_test_rom(&program));
+        assert_eq!(result.status, TestRomStatus::Failed(0x03));
+        assert_eq!(result.message, "ok")
+    }
+
+    // Regression suite over every ROM placed in testing/test_roms
+    #[test]
+    #[ignore = "needs testing/test_roms, run with cargo test -- --ignored"]
+    fn test_rom_directory() {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("testing/test_roms");
+        assert!(directory.exists(), "{} is required", directory.display());
+        let failures: Vec<String> = run_test_rom_directory(&directory).iter()
+            .filter(|(_, result)| !result.passed())
+            .map(|(rom, result)| format!("{}: {:?} {}", rom.display(), result.status, result.message))
+            .collect();
+        assert!(failures.is_empty(), "{}", failures.join("\n"))
+    }
+}
@@ -0,0 +1,184 @@
+use std::io::Write;
+use log::warn;
+use crate::bus::CpuBus;
+use crate::flags::Flags;
+use crate::op_code::Mnemonic::{JMP, JSR};
+use crate::disassembler::{disassemble, Instruction};
+use crate::addressing::{AddressingMode, AddressingRegistry};
+use crate::util::combine_u8;
+
+// Registers as they were before the traced instruction ran
+#[derive(Clone, Copy, Debug)]
+pub struct CpuState {
+    pub program_counter: u16,
+    pub acc: u8,
+    pub reg_x: u8,
+    pub reg_y: u8,
+    pub status: Flags,
+    pub stack_pointer: u8,
+    pub cycles: u64
+}
+
+// Writes one nestest.log (Nintendulator) formatted line per instruction
+pub struct Tracer {
+    sink: Box<dyn Write>,
+    enabled: bool
+}
+
+impl Tracer {
+    pub fn new(sink: Box<dyn Write>) -> Tracer {
+        Tracer {
+            sink,
+            enabled: true
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn trace<B: CpuBus>(&mut self, bus: &B, state: &CpuState) {
+        if !self.enabled {
+            return
+        }
+        let line = format_line(bus, state);
+        if let Err(error) = writeln!(self.sink, "{}", line) {
+            warn!("Could not write trace line: {}", error);
+        }
+    }
+}
+
+// Memory is peeked, tracing an instruction touching PPU ports leaves them alone
+pub fn format_line<B: CpuBus>(bus: &B, state: &CpuState) -> String {
+    let instruction = disassemble(bus, state.program_counter);
+    let effective = format_effective(bus, state, &instruction);
+    let (scanline, dot) = bus.ppu_position();
+    format!(
+        "{:04X}  {:<8} {}{:<32}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+        state.program_counter,
+        instruction.raw_bytes(),
+        if instruction.info.official { ' ' } else { '*' },
+        format!("{}{}", instruction, effective),
+        state.acc,
+        state.reg_x,
+        state.reg_y,
+        state.status.bits(),
+        state.stack_pointer,
+        scanline,
+        dot,
+        state.cycles
+    )
+}
+
+// Effective address and the value stored there before the instruction runs
+fn format_effective<B: CpuBus>(bus: &B, state: &CpuState, instruction: &Instruction) -> String {
+    let argument = instruction.argument();
+    let index = match instruction.info.addressing.register {
+        Some(AddressingRegistry::X) => state.reg_x,
+        Some(AddressingRegistry::Y) => state.reg_y,
+        _ => 0
+    };
+    match instruction.info.addressing.mode {
+        AddressingMode::ZeroPage => format!(" = {:02X}", bus.peek(argument)),
+        AddressingMode::ZeroPageIndexed => {
+            let address = (argument as u8).wrapping_add(index) as u16;
+            format!(" @ {:02X} = {:02X}", address, bus.peek(address))
+        },
+        AddressingMode::Absolute => match instruction.info.mnemonic {
+            JMP | JSR => String::new(),
+            _ => format!(" = {:02X}", bus.peek(argument))
+        },
+        AddressingMode::AbsoluteIndexed => {
+            let address = argument.wrapping_add(index as u16);
+            format!(" @ {:04X} = {:02X}", address, bus.peek(address))
+        },
+        AddressingMode::Indirect => {
+            // The high byte is read without carrying into the pointer's page
+            let lsb = bus.peek(argument);
+            let msb = bus.peek((argument & 0xFF00) | (argument.wrapping_add(1) & 0x00FF));
+            format!(" = {:04X}", combine_u8(lsb, msb))
+        },
+        AddressingMode::IndexedIndirect => {
+            let pointer = (argument as u8).wrapping_add(state.reg_x);
+            let lsb = bus.peek(pointer as u16);
+            let msb = bus.peek(pointer.wrapping_add(1) as u16);
+            let address = combine_u8(lsb, msb);
+            format!(" @ {:02X} = {:04X} = {:02X}", pointer, address, bus.peek(address))
+        },
+        AddressingMode::IndirectIndexed => {
+            let lsb = bus.peek(argument);
+            let msb = bus.peek((argument as u8).wrapping_add(1) as u16);
+            let base = combine_u8(lsb, msb);
+            let address = base.wrapping_add(state.reg_y as u16);
+            format!(" = {:04X} @ {:04X} = {:02X}", base, address, bus.peek(address))
+        },
+        _ => String::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flat_bus::FlatBus;
+
+    fn create_test_bus(program: &[u8]) -> FlatBus {
+        FlatBus::with_program(program, 0x0400)
+    }
+
+    fn create_test_state() -> CpuState {
+        CpuState {
+            program_counter: 0x0400,
+            acc: 0,
+            reg_x: 0,
+            reg_y: 0,
+            status: Flags::default(),
+            stack_pointer: 0xFD,
+            cycles: 7
+        }
+    }
+
+    #[test]
+    fn test_jump_absolute() {
+        let bus = create_test_bus(&[0x4C, 0xF5, 0xC5]);
+        assert_eq!(
+            format_line(&bus, &create_test_state()),
+            "0400  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0,  0 CYC:7"
+        )
+    }
+
+    #[test]
+    fn test_indirect_indexed() {
+        let mut bus = create_test_bus(&[0xB1, 0x89]);
+        bus.store(0x00, 0x0089);
+        bus.store(0x03, 0x008A);
+        bus.store(0x89, 0x0304);
+        let mut state = create_test_state();
+        state.reg_y = 0x04;
+        assert_eq!(
+            format_line(&bus, &state),
+            "0400  B1 89     LDA ($89),Y = 0300 @ 0304 = 89  A:00 X:00 Y:04 P:24 SP:FD PPU:  0,  0 CYC:7"
+        )
+    }
+
+    #[test]
+    fn test_unofficial_marker() {
+        let bus = create_test_bus(&[0x04, 0xA9]);
+        assert_eq!(
+            format_line(&bus, &create_test_state()),
+            "0400  04 A9    *NOP $A9 = 00                    A:00 X:00 Y:00 P:24 SP:FD PPU:  0,  0 CYC:7"
+        )
+    }
+
+    #[test]
+    fn test_accumulator_and_relative() {
+        let bus = create_test_bus(&[0x4A, 0xF0, 0xFD]);
+        let mut state = create_test_state();
+        assert!(format_line(&bus, &state).starts_with("0400  4A        LSR A  "));
+        state.program_counter = 0x0401;
+        assert!(format_line(&bus, &state).starts_with("0401  F0 FD     BEQ $0400  "))
+    }
+}
@@ -1,6 +1,4 @@
-use std::u8;
 use std::ops::BitOr;
-use log::{info, warn};
 use std::path::Path;
 use std::fs::File;
 use std::io::Read;
@@ -9,10 +7,6 @@ pub fn combine_u8(lsb: u8, msb: u8) -> u16 {
     ((msb as u16) << 8).bitor(lsb as u16)
 }
 
-pub fn combine_nibbles(lsb: u8, msb: u8) -> u8 {
-    (msb << 4).bitor(lsb)
-}
-
 pub fn msb(value: u8) -> u8 {
     value >> 7
 }
@@ -32,13 +26,14 @@ pub fn lsb(value: u8) -> u8 {
 pub fn read_file(path: &Path) -> Vec<u8> {
     let mut file = File::open(path).unwrap();
     let mut data = Vec::new();
-    file.read_to_end(&mut data);
-    return data;
+    file.read_to_end(&mut data).unwrap();
+    data
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use log::info;
 
     #[test]
     fn test_get_first_bit() {
@@ -88,11 +83,4 @@ mod tests {
         let msb = 0x34;
         assert_eq!(combine_u8(lsb, msb), 0x3412)
     }
-
-    #[test]
-    fn test_combine_nibbles() {
-        let lsb = 0x01;
-        let msb = 0x02;
-        assert_eq!(combine_nibbles(lsb, msb), 0x12)
-    }
 }
\ No newline at end of file
@@ -1,35 +1,24 @@
 import logging
-import subprocess
+import sys
 
 
-def parse():
-    with open("desired_output.txt") as fi:
-        with open("parsed_desired_output.txt", "w") as fo:
-            for line in fi.readlines():
-                opcode = line[0:4]
-                status_start = line.find("A:")
-                status_end = line.find("SP") + 5
-                output_line = "{} {}\n".format(opcode, line[status_start: status_end])
-                fo.write(output_line)
-
-
-def compare():
-    result = []
-    with open("output.txt") as actual:
-        with open("parsed_desired_output.txt") as expected:
+def compare(actual_path, expected_path):
+    with open(actual_path) as actual:
+        with open(expected_path) as expected:
             counter = 0
-            for actual_line, expected_line in zip(actual.readlines(), expected.readlines()):
+            for actual_line, expected_line in zip(actual, expected):
                 counter += 1
-                if not actual_line:
-                    logging.warning("Missing line {}".format(counter))
-                    logging.warning(result)
-                    return
-                if actual_line != expected_line:
-                    result.append(counter)
-    logging.warning(result)
+                if actual_line.rstrip() != expected_line.rstrip():
+                    logging.warning("Diverged at line {}".format(counter))
+                    logging.warning("expected: {}".format(expected_line.rstrip()))
+                    logging.warning("actual:   {}".format(actual_line.rstrip()))
+                    return False
+    logging.warning("{} lines match".format(counter))
+    return True
 
 
 if __name__ == "__main__":
-    # subprocess.run(["../cargo run --package r_nes --bin r_nes"])
-    parse()
-    compare()
+    # cargo run --package r_nes --bin r_nes -- rom/nestest.nes --trace output.txt
+    actual_path = sys.argv[1] if len(sys.argv) > 1 else "output.txt"
+    expected_path = sys.argv[2] if len(sys.argv) > 2 else "nestest.log"
+    sys.exit(0 if compare(actual_path, expected_path) else 1)